@@ -39,26 +39,26 @@ done
 for NUM in $(seq 1 $END) 
 do 
     echo "Run MAX - Network - $NUM"
-    ./target/release/bpr --log "$OUTPUTDIR/max/network" --na $((16 * $NUM)) --nb $((400 * $NUM)) --vs 10 $COMMON --goal MAX --poisson
+    ./target/release/bpr --log "$OUTPUTDIR/max/network" --na $((16 * $NUM)) --nb $((400 * $NUM)) --vs 10 $COMMON --goal MAX --distribution POISSON
 done
 
 # Sum Network 
 for NUM in $(seq 1 $END) 
 do 
     echo "Run SUM - Network - $NUM"
-    ./target/release/bpr --log "$OUTPUTDIR/sum/network" --na $((16 * $NUM)) --nb $((400 * $NUM)) --vs 10 $COMMON --goal SUM --poisson
+    ./target/release/bpr --log "$OUTPUTDIR/sum/network" --na $((16 * $NUM)) --nb $((400 * $NUM)) --vs 10 $COMMON --goal SUM --distribution POISSON
 done
 
 # Cov Network
 for NUM in $(seq 1 10) 
 do 
     echo "Run COV - Network - $NUM"
-    ./target/release/bpr --log "$OUTPUTDIR/cov/network" --na $((16 * $NUM)) --nb $((400 * $NUM)) --vs 10 $COMMON --goal COV --poisson --ipopt
+    ./target/release/bpr --log "$OUTPUTDIR/cov/network" --na $((16 * $NUM)) --nb $((400 * $NUM)) --vs 10 $COMMON --goal COV --distribution POISSON --ipopt
 done
 
 # Cov Network
 for NUM in $(seq 11 $END) 
 do 
     echo "Run COV - Network - $NUM"
-    ./target/release/bpr --log "$OUTPUTDIR/cov/network" --na $((16 * $NUM)) --nb $((400 * $NUM)) --vs 10 $COMMON --goal COV --poisson
+    ./target/release/bpr --log "$OUTPUTDIR/cov/network" --na $((16 * $NUM)) --nb $((400 * $NUM)) --vs 10 $COMMON --goal COV --distribution POISSON
 done
@@ -1,12 +1,117 @@
-use std::{collections::BinaryHeap, time::Instant};
+use std::{cmp::Ordering, collections::BinaryHeap, time::Instant};
 
 use ez_bitset::bitset::*;
 
 use crate::{
     distributions::WeightedDistribution,
-    model::{BipartiteRegulatorProbing, Instance, ProbeMax, ProbeMaxInstance},
+    is_close,
+    model::{BipartiteRegulatorProbing, Edge, Instance, ProbeMax, ProbeMaxInstance},
 };
 
+/// Does a regulator of the given cost fit into the remaining budget
+#[inline]
+fn fits(cost: f64, remaining: f64) -> bool {
+    cost <= remaining || is_close(cost, remaining)
+}
+
+/// Regulators greedily selected from the given regulators with the values of their edges (aligned with
+/// `get_positions`, subject to the group constraint of the model) and their weighted coverage
+fn greedy_cov_selection(
+    bpr: &BipartiteRegulatorProbing,
+    rows: &[(usize, &[f64])],
+    l: usize,
+) -> (Vec<usize>, f64) {
+    let mut current_values: Vec<f64> = vec![0.0; bpr.get_nb()];
+    let mut chosen_rows = BitSet::new_all_set(rows.len());
+    let mut group_counts = bpr.new_group_counts();
+    let mut selection: Vec<usize> = Vec::with_capacity(l);
+    let mut greedy_value = 0.0;
+
+    for _ in 0..l.min(rows.len()) {
+        let best = chosen_rows
+            .iter()
+            .filter(|i| bpr.can_select(&group_counts, rows[*i].0))
+            .map(|i| -> (f64, usize) {
+                let (a, values) = rows[i];
+                (
+                    bpr.get_positions(a)
+                        .iter()
+                        .zip(values)
+                        .map(|(&b, &x)| {
+                            bpr.get_position_weight(b) * (x - current_values[b]).max(0.0)
+                        })
+                        .sum(),
+                    i,
+                )
+            })
+            .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap());
+
+        let (inc, argmax) = match best {
+            Some(best) => best,
+            None => break,
+        };
+
+        greedy_value += inc;
+        chosen_rows.unset_bit(argmax);
+        let (a, values) = rows[argmax];
+        selection.push(a);
+        bpr.select(&mut group_counts, a);
+        for (&b, &x) in bpr.get_positions(a).iter().zip(values) {
+            current_values[b] = current_values[b].max(x);
+        }
+    }
+
+    (selection, greedy_value)
+}
+
+/// Gain `sum_b w_b * E[X_ab | X_ab > current_values[b]] * P[X_ab > current_values[b]]` of probing Regulator a
+fn expected_gain(bpr: &BipartiteRegulatorProbing, a: usize, current_values: &[f64]) -> f64 {
+    bpr.get_regulator_edges(a)
+        .map(|(b, edge)| bpr.get_position_weight(b) * edge.expected_greater(current_values[b]))
+        .sum()
+}
+
+/// Expected values of all edges (aligned with `get_positions`)
+fn expected_rows(bpr: &BipartiteRegulatorProbing) -> Vec<Vec<f64>> {
+    (0..bpr.get_na())
+        .map(|a| {
+            bpr.get_regulator(a)
+                .iter()
+                .map(|edge| edge.expected_value())
+                .collect()
+        })
+        .collect()
+}
+
+/// Regulators greedily selected on the estimated values of all edges
+fn greedy_cov_rows(bpr: &BipartiteRegulatorProbing, rows: &[Vec<f64>], l: usize) -> Vec<usize> {
+    let rows: Vec<(usize, &[f64])> = rows.iter().map(|r| r.as_slice()).enumerate().collect();
+    greedy_cov_selection(bpr, &rows, l).0
+}
+
+/// Next edge `(a, i)` to probe: the unprobed edge with the largest weighted expected excess over the value of its
+/// Position, or `None` if all edges are probed
+fn best_unprobed_edge(
+    bpr: &BipartiteRegulatorProbing,
+    probed: &[Vec<bool>],
+    position_values: &[f64],
+) -> Option<(usize, usize)> {
+    (0..bpr.get_na())
+        .flat_map(|a| {
+            bpr.get_regulator_edges(a)
+                .enumerate()
+                .filter(move |(i, _)| !probed[a][*i])
+                .map(move |(i, (b, edge))| {
+                    (
+                        (a, i),
+                        bpr.get_position_weight(b) * edge.expected_excess(position_values[b]),
+                    )
+                })
+        })
+        .max_by(|(_, x), (_, y)| x.partial_cmp(y).unwrap())
+        .map(|(edge, _)| edge)
+}
+
 impl ProbeMax {
     /// Computes a Non-Adaptive Policy for this ProbeMax Instance
     pub fn compute_namp_policy(boxes: &[WeightedDistribution]) -> (Vec<usize>, f64) {
@@ -24,12 +129,68 @@ impl ProbeMax {
             timer.elapsed().as_secs_f64(),
         )
     }
+
+    /// Computes a Non-Adaptive Policy within a budget: the boxes by decreasing ratio of expected value and
+    /// probing cost, skipping those that do not fit into the remaining budget
+    pub fn compute_namp_budget_policy(&self, budget: f64) -> Vec<usize> {
+        let bpr = self.get_model();
+
+        let mut ratio_pairs: Vec<(usize, f64)> = self
+            .get_boxes()
+            .iter()
+            .enumerate()
+            .map(|(i, b)| (i, b.expected_value() / bpr.get_regulator_cost(i)))
+            .collect();
+        ratio_pairs.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap());
+
+        let mut remaining = budget;
+        ratio_pairs
+            .into_iter()
+            .map(|(i, _)| i)
+            .filter(|i| {
+                let cost = bpr.get_regulator_cost(*i);
+                if fits(cost, remaining) {
+                    remaining -= cost;
+                    true
+                } else {
+                    false
+                }
+            })
+            .collect()
+    }
+}
+
+/// Totally ordered realization value to allow storing them in a `BinaryHeap`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct HeapValue(f64);
+
+impl Eq for HeapValue {}
+
+impl PartialOrd for HeapValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
 }
 
 impl ProbeMaxInstance<'_> {
-    pub fn adaptive_policy(&self, k: usize, l: usize) -> (usize, f64) {
+    pub fn adaptive_policy(&self, k: usize, l: usize) -> (f64, f64) {
         let timer = Instant::now();
 
+        let probed_boxes = self.adaptive_policy_boxes(k, l);
+
+        (
+            self.eval_policy(&probed_boxes, l),
+            self.get_probemax().get_policy_time() + timer.elapsed().as_secs_f64(),
+        )
+    }
+
+    pub fn adaptive_policy_boxes(&self, k: usize, l: usize) -> Vec<usize> {
         let n = self.get_probemax().get_n();
 
         let mut probed_subset: Vec<usize> = self
@@ -40,27 +201,25 @@ impl ProbeMaxInstance<'_> {
             .take(l)
             .collect();
         if k == l {
-            return (
-                probed_subset.iter().map(|x| self.get_realization(*x)).sum(),
-                self.get_probemax().get_policy_time() + timer.elapsed().as_secs_f64(),
-            );
+            return probed_subset;
         }
 
         let mut unprobed_regulators = BitSet::new_all_set_but(n, probed_subset.clone());
         let mut values_heap = BinaryHeap::from(
             probed_subset
                 .iter()
-                .map(|x| self.get_realization(*x))
-                .collect::<Vec<usize>>(),
+                .map(|x| HeapValue(self.get_estimate(*x)))
+                .collect::<Vec<HeapValue>>(),
         );
 
         while probed_subset.len() < k {
-            let lval: usize = values_heap
+            let lval: f64 = values_heap
                 .clone()
                 .into_iter_sorted()
                 .take(l)
                 .last()
-                .unwrap();
+                .unwrap()
+                .0;
 
             let argmax: usize = unprobed_regulators
                 .iter()
@@ -73,13 +232,114 @@ impl ProbeMaxInstance<'_> {
 
             probed_subset.push(argmax);
             unprobed_regulators.unset_bit(argmax);
-            values_heap.push(self.get_realization(argmax));
+            values_heap.push(HeapValue(self.get_estimate(argmax)));
         }
 
-        (
-            values_heap.into_iter_sorted().take(l).sum(),
-            self.get_probemax().get_policy_time() + timer.elapsed().as_secs_f64(),
-        )
+        probed_subset
+    }
+
+    /// Adaptive policy within a budget: repeatedly probe the box with the largest ratio of expected gain
+    /// and probing cost among those that fit into the remaining budget. As long as fewer than `l` boxes are
+    /// probed, the gain of a box is its expected value and afterwards its expectation above the `l`-th best probed value.
+    pub fn adaptive_policy_boxes_budget(&self, budget: f64, l: usize) -> Vec<usize> {
+        let pm = self.get_probemax();
+        let bpr = pm.get_model();
+
+        let mut remaining = budget;
+        let mut probed_subset: Vec<usize> = Vec::new();
+        let mut unprobed_regulators = BitSet::new_all_set(pm.get_n());
+        let mut values_heap: BinaryHeap<HeapValue> = BinaryHeap::new();
+
+        loop {
+            let lval: Option<f64> = if probed_subset.len() < l {
+                None
+            } else {
+                values_heap
+                    .clone()
+                    .into_iter_sorted()
+                    .take(l)
+                    .last()
+                    .map(|x| x.0)
+            };
+
+            let next = unprobed_regulators
+                .iter()
+                .filter(|x| fits(bpr.get_regulator_cost(*x), remaining))
+                .map(|x| -> (usize, f64) {
+                    let gain = match lval {
+                        None => pm.get_box(x).expected_value(),
+                        Some(lval) => pm.get_box(x).expected_greater(lval),
+                    };
+                    (x, gain / bpr.get_regulator_cost(x))
+                })
+                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+            let argmax = match next {
+                Some((argmax, _)) => argmax,
+                None => break,
+            };
+
+            probed_subset.push(argmax);
+            unprobed_regulators.unset_bit(argmax);
+            remaining -= bpr.get_regulator_cost(argmax);
+            values_heap.push(HeapValue(self.get_estimate(argmax)));
+        }
+
+        probed_subset
+    }
+
+    /// Batched adaptive policy probing `k` boxes in `rounds` rounds of (about) `k / rounds` boxes. Within a round,
+    /// the boxes are chosen like in `adaptive_policy_boxes` where the boxes of the current round count with their
+    /// expected values; their realizations are only revealed before the next round. `rounds = k` is fully adaptive.
+    pub fn batched_policy_boxes(&self, k: usize, l: usize, rounds: usize) -> Vec<usize> {
+        assert!(rounds > 0);
+        let pm = self.get_probemax();
+
+        let mut probed_subset: Vec<usize> = Vec::with_capacity(k);
+        let mut unprobed_regulators = BitSet::new_all_set(pm.get_n());
+        let mut values_heap: BinaryHeap<HeapValue> = BinaryHeap::new();
+
+        for round in 0..rounds {
+            let round_start = probed_subset.len();
+            let mut round_heap = values_heap.clone();
+
+            while probed_subset.len() < k * (round + 1) / rounds {
+                let lval: Option<f64> = if round_heap.len() < l {
+                    None
+                } else {
+                    round_heap
+                        .clone()
+                        .into_iter_sorted()
+                        .take(l)
+                        .last()
+                        .map(|x| x.0)
+                };
+
+                let argmax: usize = unprobed_regulators
+                    .iter()
+                    .map(|x| -> (usize, f64) {
+                        let b = pm.get_box(x);
+                        (
+                            x,
+                            lval.map_or(b.expected_value(), |v| b.expected_greater(v)),
+                        )
+                    })
+                    .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+                    .unwrap()
+                    .0;
+
+                probed_subset.push(argmax);
+                unprobed_regulators.unset_bit(argmax);
+                round_heap.push(HeapValue(pm.get_box(argmax).expected_value()));
+            }
+
+            // Reveal the boxes of this round
+            for x in &probed_subset[round_start..] {
+                values_heap.push(HeapValue(self.get_estimate(*x)));
+            }
+        }
+
+        probed_subset
     }
 }
 
@@ -92,57 +352,62 @@ impl BipartiteRegulatorProbing {
 
         let timer = Instant::now();
 
-        let (mut probed_subset, l_time): (Vec<usize>, f64) = self.get_l_policy(l);
-        let mut probed_values: Vec<f64> = (0..self.get_nb())
-            .map(|b| {
-                probed_subset
-                    .iter()
-                    .map(|a| self.get_edge(*a, b).expected_value())
-                    .max_by(|a, b| a.partial_cmp(b).unwrap())
-                    .unwrap_or(0.0)
-            })
-            .collect();
+        // Prefixes of other policies may violate the group constraint
+        let (mut probed_subset, l_time): (Vec<usize>, f64) = if self.has_group_constraint() {
+            (Vec::new(), 0.0)
+        } else {
+            self.get_l_policy(l)
+        };
+        let mut group_counts = self.new_group_counts();
+        let mut probed_values: Vec<f64> = vec![0.0; self.get_nb()];
+        for a in &probed_subset {
+            for (b, edge) in self.get_regulator_edges(*a) {
+                probed_values[b] = edge.expected_value().max(probed_values[b]);
+            }
+        }
         let mut unprobed_regulators =
             BitSet::new_all_set_but(self.get_na(), probed_subset.iter().copied());
 
         while probed_subset.len() < l {
             // Compute next Regulator to probe
-            let argmax: usize = unprobed_regulators
+            let argmax: usize = match unprobed_regulators
                 .iter()
+                .filter(|a| self.can_select(&group_counts, *a))
                 .map(|a| -> (usize, f64) {
                     (
                         a,
-                        (0..self.get_nb())
-                            .map(|b| {
-                                self.get_edge(a, b)
-                                    .expected_greater(probed_values[b].floor() as usize)
+                        self.get_regulator_edges(a)
+                            .map(|(b, edge)| {
+                                self.get_position_weight(b)
+                                    * edge.expected_greater(probed_values[b])
                             })
                             .sum(),
                     )
                 })
                 .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
-                .unwrap()
-                .0;
+            {
+                Some((argmax, _)) => argmax,
+                // No more Regulators can be selected
+                None => break,
+            };
 
             // Add argmax to probed Regulators
             probed_subset.push(argmax);
             unprobed_regulators.unset_bit(argmax);
-            for b in 0..self.get_nb() {
-                probed_values[b] = self
-                    .get_edge(argmax, b)
-                    .expected_value()
-                    .max(probed_values[b]);
+            self.select(&mut group_counts, argmax);
+            for (b, edge) in self.get_regulator_edges(argmax) {
+                probed_values[b] = edge.expected_value().max(probed_values[b]);
             }
         }
 
-        for _ in l..k {
+        while probed_subset.len() < k {
             let argmax: usize = unprobed_regulators
                 .iter()
                 .map(|a| -> (usize, f64) {
                     (
                         a,
-                        (0..self.get_nb())
-                            .map(|b| self.get_edge(a, b).expected_value())
+                        self.get_regulator_edges(a)
+                            .map(|(b, edge)| self.get_position_weight(b) * edge.expected_value())
                             .sum(),
                     )
                 })
@@ -162,10 +427,94 @@ impl BipartiteRegulatorProbing {
     pub fn compute_namp_ext_cov_policy(&mut self, k: usize) {
         self.compute_namp_cov_policy(k, k);
     }
+
+    /// Non-Adaptive COV policy within a budget: like `compute_namp_cov_policy`, but the next regulator is the
+    /// one with the largest ratio of expected gain and probing cost among those that fit into the remaining budget
+    pub fn compute_namp_cov_budget_policy(&self, budget: f64, l: usize) -> Vec<usize> {
+        let mut remaining = budget;
+        let mut probed_subset: Vec<usize> = Vec::new();
+        let mut probed_values: Vec<f64> = vec![0.0; self.get_nb()];
+        let mut unprobed_regulators = BitSet::new_all_set(self.get_na());
+        // Regulators which are expected to be selected (at most l and subject to the group constraint)
+        let mut selected = 0;
+        let mut group_counts = self.new_group_counts();
+
+        loop {
+            let is_selectable = |a: usize| selected < l && self.can_select(&group_counts, a);
+
+            let next = unprobed_regulators
+                .iter()
+                .filter(|a| fits(self.get_regulator_cost(*a), remaining))
+                .map(|a| -> (usize, f64) {
+                    let gain: f64 = self
+                        .get_regulator_edges(a)
+                        .map(|(b, edge)| {
+                            self.get_position_weight(b)
+                                * if is_selectable(a) {
+                                    edge.expected_greater(probed_values[b])
+                                } else {
+                                    edge.expected_value()
+                                }
+                        })
+                        .sum();
+                    (a, gain / self.get_regulator_cost(a))
+                })
+                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+            let argmax = match next {
+                Some((argmax, _)) => argmax,
+                None => break,
+            };
+
+            if is_selectable(argmax) {
+                for (b, edge) in self.get_regulator_edges(argmax) {
+                    probed_values[b] = edge.expected_value().max(probed_values[b]);
+                }
+                selected += 1;
+                self.select(&mut group_counts, argmax);
+            }
+            probed_subset.push(argmax);
+            unprobed_regulators.unset_bit(argmax);
+            remaining -= self.get_regulator_cost(argmax);
+        }
+
+        probed_subset
+    }
+
+    /// Non-Adaptive COV policy probing single edges: select `l` regulators greedily by the expected values of their
+    /// edges and repeatedly probe the edge with the largest expected excess over the expected value of its Position.
+    /// The expected value of the Position then becomes the expected maximum of both. Returns at most `budget` edges `(a, b)`.
+    pub fn compute_namp_edge_policy(&self, budget: usize, l: usize) -> Vec<Edge> {
+        let mut probed: Vec<Vec<bool>> = (0..self.get_na())
+            .map(|a| vec![false; self.get_degree(a)])
+            .collect();
+        let mut probed_edges: Vec<Edge> = Vec::with_capacity(budget);
+
+        let mut position_values: Vec<f64> = vec![0.0; self.get_nb()];
+        for a in greedy_cov_rows(self, &expected_rows(self), l) {
+            for (b, edge) in self.get_regulator_edges(a) {
+                position_values[b] = edge.expected_value().max(position_values[b]);
+            }
+        }
+
+        while probed_edges.len() < budget {
+            let (a, i) = match best_unprobed_edge(self, &probed, &position_values) {
+                Some(edge) => edge,
+                None => break,
+            };
+
+            let b = self.get_positions(a)[i];
+            position_values[b] += self.get_regulator(a)[i].expected_excess(position_values[b]);
+            probed[a][i] = true;
+            probed_edges.push((a, b));
+        }
+
+        probed_edges
+    }
 }
 
 impl Instance<'_> {
-    pub fn adaptive_policy(&self, k: usize, l: usize) -> (usize, f64) {
+    pub fn adaptive_policy(&self, k: usize, l: usize) -> (f64, f64) {
         let timer = Instant::now();
 
         let probed_regulators = self.adaptive_policy_regulators(k, l);
@@ -178,22 +527,48 @@ impl Instance<'_> {
 
     pub fn adaptive_policy_regulators(&self, k: usize, l: usize) -> Vec<usize> {
         let mut unprobed_regulators = BitSet::new_all_set(self.get_model().get_na());
-        let mut current_values: Vec<usize> = vec![0; self.get_model().get_nb()];
+        let mut current_values: Vec<f64> = vec![0.0; self.get_model().get_nb()];
         let mut probed_regulators: Vec<usize> = Vec::with_capacity(k);
+        let mut group_counts = self.get_model().new_group_counts();
 
-        for _ in 0..l {
-            let argmax = unprobed_regulators
+        while probed_regulators.len() < l {
+            let argmax = match unprobed_regulators
+                .iter()
+                .filter(|a| self.get_model().can_select(&group_counts, *a))
+                .map(|a| (a, expected_gain(self.get_model(), a, &current_values)))
+                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            {
+                Some((argmax, _)) => argmax,
+                // No more Regulators can be selected
+                None => break,
+            };
+
+            unprobed_regulators.unset_bit(argmax);
+            probed_regulators.push(argmax);
+            self.get_model().select(&mut group_counts, argmax);
+            for (b, x) in self.get_regulator_estimates(argmax) {
+                current_values[b] = x.max(current_values[b]);
+            }
+        }
+
+        // Lookahead values of the Positions (all 0 between lookaheads) and the Positions set in a lookahead
+        let mut temp_values: Vec<f64> = vec![0.0; self.get_model().get_nb()];
+        let mut temp_positions: Vec<usize> = Vec::new();
+
+        while probed_regulators.len() < k {
+            let argmax: usize = unprobed_regulators
                 .iter()
                 .map(|a| -> (usize, f64) {
                     (
                         a,
-                        (0..self.get_model().get_nb())
-                            .map(|b| {
-                                self.get_model()
-                                    .get_edge(a, b)
-                                    .expected_greater(current_values[b])
-                            })
-                            .sum(),
+                        self.lookahead_value(
+                            &unprobed_regulators,
+                            Some(a),
+                            l,
+                            |temp_a| temp_a == a,
+                            &mut temp_values,
+                            &mut temp_positions,
+                        ),
                     )
                 })
                 .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
@@ -202,88 +577,599 @@ impl Instance<'_> {
 
             unprobed_regulators.unset_bit(argmax);
             probed_regulators.push(argmax);
-            for b in 0..self.get_model().get_nb() {
-                current_values[b] = self.get_realization(argmax, b).max(current_values[b]);
+        }
+
+        probed_regulators
+    }
+
+    /// Value of the greedy selection of `l` of the probed regulators and the optional candidate in the lookahead of
+    /// the adaptive COV policies. Regulators with `unrevealed(temp_a)` count with the expected values of their edges,
+    /// all others with their estimates. `temp_values` must be all 0 and is reset afterwards.
+    fn lookahead_value(
+        &self,
+        unprobed_regulators: &BitSet,
+        candidate: Option<usize>,
+        l: usize,
+        unrevealed: impl Fn(usize) -> bool,
+        temp_values: &mut [f64],
+        temp_positions: &mut Vec<usize>,
+    ) -> f64 {
+        let mut temp_subset =
+            BitSet::new_all_set_but(self.get_model().get_na(), unprobed_regulators.iter());
+        if let Some(a) = candidate {
+            temp_subset.set_bit(a);
+        }
+
+        let mut temp_counts = self.get_model().new_group_counts();
+
+        // Edge values of temp_a in the lookahead
+        let temp_edges = |temp_a: usize| -> Box<dyn Iterator<Item = (usize, f64)> + '_> {
+            if unrevealed(temp_a) {
+                Box::new(
+                    self.get_model()
+                        .get_regulator_edges(temp_a)
+                        .map(|(b, edge)| (b, edge.expected_value())),
+                )
+            } else {
+                Box::new(self.get_regulator_estimates(temp_a))
+            }
+        };
+
+        for _ in 0..l {
+            let temp_argmax: usize = match temp_subset
+                .iter()
+                .filter(|temp_a| self.get_model().can_select(&temp_counts, *temp_a))
+                .map(|temp_a| -> (usize, f64) {
+                    (
+                        temp_a,
+                        temp_edges(temp_a)
+                            .map(|(b, x)| {
+                                self.get_model().get_position_weight(b)
+                                    * if x > temp_values[b] {
+                                        x - temp_values[b]
+                                    } else {
+                                        0.0
+                                    }
+                            })
+                            .sum(),
+                    )
+                })
+                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+            {
+                Some((temp_argmax, _)) => temp_argmax,
+                None => break,
+            };
+
+            temp_subset.unset_bit(temp_argmax);
+            self.get_model().select(&mut temp_counts, temp_argmax);
+            for (b, x) in temp_edges(temp_argmax) {
+                if x > temp_values[b] {
+                    if temp_values[b] == 0.0 {
+                        temp_positions.push(b);
+                    }
+                    temp_values[b] = x - temp_values[b];
+                }
             }
         }
 
-        for _ in l..k {
-            let argmax: usize = unprobed_regulators
+        // Sum in the order of the Positions and reset the lookahead values
+        temp_positions.sort_unstable();
+        temp_positions
+            .drain(..)
+            .map(|b| self.get_model().get_position_weight(b) * std::mem::take(&mut temp_values[b]))
+            .sum()
+    }
+
+    /// Adaptive COV policy within a budget: repeatedly probe the regulator with the largest ratio of expected
+    /// gain and probing cost among those that fit into the remaining budget. Once `l` regulators are probed,
+    /// the gain of a regulator is the increase of the lookahead value of `adaptive_policy_regulators`
+    /// (using the expected values of its edges), so that unit costs and a budget of k probe like it.
+    pub fn adaptive_policy_regulators_budget(&self, budget: f64, l: usize) -> Vec<usize> {
+        let bpr = self.get_model();
+
+        let mut remaining = budget;
+        let mut probed_regulators: Vec<usize> = Vec::new();
+        let mut unprobed_regulators = BitSet::new_all_set(bpr.get_na());
+        let mut current_values: Vec<f64> = vec![0.0; bpr.get_nb()];
+        // Regulators which are selected greedily (at most l and subject to the group constraint)
+        let mut selected = 0;
+        let mut group_counts = bpr.new_group_counts();
+
+        // Lookahead values of the Positions (all 0 between lookaheads) and the Positions set in a lookahead
+        let mut temp_values: Vec<f64> = vec![0.0; bpr.get_nb()];
+        let mut temp_positions: Vec<usize> = Vec::new();
+
+        loop {
+            let is_selectable = |a: usize| selected < l && bpr.can_select(&group_counts, a);
+
+            let base = self.lookahead_value(
+                &unprobed_regulators,
+                None,
+                l,
+                |_| false,
+                &mut temp_values,
+                &mut temp_positions,
+            );
+
+            let next = unprobed_regulators
                 .iter()
+                .filter(|a| fits(bpr.get_regulator_cost(*a), remaining))
                 .map(|a| -> (usize, f64) {
-                    let mut temp_subset = BitSet::new_all_set_but(
-                        self.get_model().get_na(),
-                        unprobed_regulators.iter(),
-                    );
-                    temp_subset.set_bit(a);
+                    let gain = if is_selectable(a) {
+                        bpr.get_regulator_edges(a)
+                            .map(|(b, edge)| {
+                                bpr.get_position_weight(b)
+                                    * edge.expected_greater(current_values[b])
+                            })
+                            .sum()
+                    } else {
+                        let value = self.lookahead_value(
+                            &unprobed_regulators,
+                            Some(a),
+                            l,
+                            |temp_a| temp_a == a,
+                            &mut temp_values,
+                            &mut temp_positions,
+                        );
+                        value - base
+                    };
+                    (a, gain / bpr.get_regulator_cost(a))
+                })
+                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+            let argmax = match next {
+                Some((argmax, _)) => argmax,
+                None => break,
+            };
+
+            if is_selectable(argmax) {
+                for (b, x) in self.get_regulator_estimates(argmax) {
+                    current_values[b] = x.max(current_values[b]);
+                }
+                selected += 1;
+                bpr.select(&mut group_counts, argmax);
+            }
+            probed_regulators.push(argmax);
+            unprobed_regulators.unset_bit(argmax);
+            remaining -= bpr.get_regulator_cost(argmax);
+        }
+
+        probed_regulators
+    }
+
+    /// Adaptive COV policy probing single edges: repeatedly select `l` regulators greedily by the estimates of the
+    /// probed edges and the expected values of all other edges and probe the edge with the largest expected excess
+    /// over the value of its Position in this selection. Returns at most `budget` edges `(a, b)`.
+    pub fn adaptive_policy_edges(&self, budget: usize, l: usize) -> Vec<Edge> {
+        let bpr = self.get_model();
+
+        let mut probed: Vec<Vec<bool>> = (0..bpr.get_na())
+            .map(|a| vec![false; bpr.get_degree(a)])
+            .collect();
+        let mut probed_edges: Vec<Edge> = Vec::with_capacity(budget);
+        let mut estimates = expected_rows(bpr);
+        let mut position_values: Vec<f64> = vec![0.0; bpr.get_nb()];
+
+        while probed_edges.len() < budget {
+            position_values.fill(0.0);
+            for a in greedy_cov_rows(bpr, &estimates, l) {
+                for (&b, &x) in bpr.get_positions(a).iter().zip(&estimates[a]) {
+                    position_values[b] = x.max(position_values[b]);
+                }
+            }
+
+            let (a, i) = match best_unprobed_edge(bpr, &probed, &position_values) {
+                Some(edge) => edge,
+                None => break,
+            };
+
+            estimates[a][i] = self.get_estimates(a)[i];
+            probed[a][i] = true;
+            probed_edges.push((a, bpr.get_positions(a)[i]));
+        }
+
+        probed_edges
+    }
+
+    /// Select `l` regulators greedily by the estimates of the probed edges `(a, b)` and the expected values of
+    /// all other edges (subject to the group constraint)
+    pub fn select_by_edges(&self, probed_edges: &[Edge], l: usize) -> Vec<usize> {
+        let bpr = self.get_model();
+
+        let mut estimates = expected_rows(bpr);
+        for &(a, b) in probed_edges {
+            let i = bpr
+                .get_positions(a)
+                .binary_search(&b)
+                .expect("Probed edge does not exist");
+            estimates[a][i] = self.get_estimates(a)[i];
+        }
+
+        greedy_cov_rows(bpr, &estimates, l)
+    }
+
+    /// Select `l` of the probed regulators greedily by the estimates of their edges (subject to the group constraint)
+    pub fn select_by_estimates(&self, probed_regulators: &[usize], l: usize) -> Vec<usize> {
+        let rows: Vec<(usize, &[f64])> = probed_regulators
+            .iter()
+            .map(|a| (*a, self.get_estimates(*a)))
+            .collect();
+        greedy_cov_selection(self.get_model(), &rows, l).0
+    }
 
-                    let mut temp_values: Vec<f64> = vec![0.0; self.get_model().get_nb()];
+    /// Batched adaptive COV policy probing `k` regulators in `rounds` rounds of (about) `k / rounds` regulators.
+    /// The next regulator is chosen like in `adaptive_policy_regulators`, where the regulators of the current round
+    /// count with the expected values of their edges; their estimates are only revealed before the next round.
+    /// `rounds = k` is `adaptive_policy_regulators`.
+    pub fn batched_policy_regulators(&self, k: usize, l: usize, rounds: usize) -> Vec<usize> {
+        assert!(rounds > 0);
+        let bpr = self.get_model();
 
-                    for _ in 0..l {
-                        let temp_argmax: usize = temp_subset
+        let mut unprobed_regulators = BitSet::new_all_set(bpr.get_na());
+        let mut current_values: Vec<f64> = vec![0.0; bpr.get_nb()];
+        let mut probed_regulators: Vec<usize> = Vec::with_capacity(k);
+        let mut group_counts = bpr.new_group_counts();
+        // Regulators probed before `l` regulators were probed or no more regulators could be selected
+        let mut selected = 0;
+
+        let mut temp_values: Vec<f64> = vec![0.0; bpr.get_nb()];
+        let mut temp_positions: Vec<usize> = Vec::new();
+
+        for round in 0..rounds {
+            let round_start = probed_regulators.len();
+
+            while probed_regulators.len() < k * (round + 1) / rounds {
+                let next = if selected == probed_regulators.len() && selected < l {
+                    unprobed_regulators
+                        .iter()
+                        .filter(|a| bpr.can_select(&group_counts, *a))
+                        .map(|a| (a, expected_gain(bpr, a, &current_values)))
+                        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+                } else {
+                    None
+                };
+
+                let argmax = match next {
+                    Some((argmax, _)) => {
+                        selected += 1;
+                        bpr.select(&mut group_counts, argmax);
+                        for (b, edge) in bpr.get_regulator_edges(argmax) {
+                            current_values[b] = edge.expected_value().max(current_values[b]);
+                        }
+                        argmax
+                    }
+                    None => {
+                        let round = &probed_regulators[round_start..];
+                        unprobed_regulators
                             .iter()
-                            .map(|temp_a| -> (usize, f64) {
+                            .map(|a| -> (usize, f64) {
                                 (
-                                    temp_a,
-                                    (0..self.get_model().get_nb())
-                                        .map(|b| {
-                                            if a == temp_a {
-                                                if self
-                                                    .get_model()
-                                                    .get_edge(temp_a, b)
-                                                    .expected_value()
-                                                    > temp_values[b]
-                                                {
-                                                    self.get_model()
-                                                        .get_edge(temp_a, b)
-                                                        .expected_value()
-                                                        - temp_values[b]
-                                                } else {
-                                                    0.0
-                                                }
-                                            } else if self.get_realization(temp_a, b) as f64
-                                                > temp_values[b]
-                                            {
-                                                self.get_realization(temp_a, b) as f64
-                                                    - temp_values[b]
-                                            } else {
-                                                0.0
-                                            }
-                                        })
-                                        .sum(),
+                                    a,
+                                    self.lookahead_value(
+                                        &unprobed_regulators,
+                                        Some(a),
+                                        l,
+                                        |temp_a| temp_a == a || round.contains(&temp_a),
+                                        &mut temp_values,
+                                        &mut temp_positions,
+                                    ),
                                 )
                             })
                             .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
                             .unwrap()
-                            .0;
-
-                        temp_subset.unset_bit(temp_argmax);
-                        for b in 0..self.get_model().get_nb() {
-                            if a == temp_argmax {
-                                if self.get_model().get_edge(temp_argmax, b).expected_value()
-                                    > temp_values[b]
-                                {
-                                    temp_values[b] =
-                                        self.get_model().get_edge(temp_argmax, b).expected_value()
-                                            - temp_values[b];
-                                }
-                            } else if self.get_realization(temp_argmax, b) as f64 > temp_values[b] {
-                                temp_values[b] =
-                                    self.get_realization(temp_argmax, b) as f64 - temp_values[b];
-                            }
-                        }
+                            .0
                     }
+                };
 
-                    (a, temp_values.into_iter().sum())
-                })
-                .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
-                .unwrap()
-                .0;
+                unprobed_regulators.unset_bit(argmax);
+                probed_regulators.push(argmax);
+            }
 
-            unprobed_regulators.unset_bit(argmax);
-            probed_regulators.push(argmax);
+            // Reveal the regulators of this round
+            current_values.fill(0.0);
+            for a in &probed_regulators[..selected] {
+                for (b, x) in self.get_regulator_estimates(*a) {
+                    current_values[b] = x.max(current_values[b]);
+                }
+            }
         }
 
         probed_regulators
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64;
+
+    use super::*;
+    use crate::{
+        distributions::{create_random_distribution, Reduction},
+        ip::solve_cov_instance,
+        model::GroupConstraint,
+        DistributionFamily,
+    };
+
+    /// Regulators 0 and 1 cover Positions 0 and 1 with value 10 but share a group of capacity 1,
+    /// Regulator 2 covers Position 2 with value 1 and is unconstrained
+    fn grouped_model(constrained: bool) -> BipartiteRegulatorProbing {
+        let point = |x: f64| WeightedDistribution::with_support(&[x], &[1.0]);
+        let edges = vec![
+            vec![(0, point(10.0))],
+            vec![(1, point(10.0))],
+            vec![(2, point(1.0))],
+        ];
+        let mut bpr = BipartiteRegulatorProbing::new_sparse(3, 3, edges, 0);
+        if constrained {
+            bpr.set_group_constraint(GroupConstraint::new(vec![Some(0), Some(0), None], vec![1]));
+        }
+        bpr
+    }
+
+    #[test]
+    fn cov_policies_respect_group_capacity() {
+        let (k, l) = (3, 2);
+        for constrained in [false, true] {
+            let mut bpr = grouped_model(constrained);
+            let expected = if constrained { 11.0 } else { 20.0 };
+
+            bpr.compute_namp_cov_policy(k, l);
+            let instance = bpr.create_instance(0);
+            assert_eq!(instance.get_opt_cov_value(l), expected);
+            assert_eq!(instance.eval_policy(&[0, 1, 2], l), expected);
+
+            let rows: Vec<(usize, &[f64])> =
+                (0..3).map(|a| (a, instance.get_estimates(a))).collect();
+            let (selection, value) = greedy_cov_selection(&bpr, &rows, l);
+            assert!(bpr.is_feasible_selection(&selection));
+            assert_eq!(value, expected);
+
+            // The first l probes of both policies must be a feasible selection
+            let namp = bpr.get_policy(k, l).unwrap();
+            let amp = instance.adaptive_policy_regulators(k, l);
+            for policy in [namp, &amp] {
+                assert!(bpr.is_feasible_selection(&policy[..l]));
+                assert_eq!(instance.eval_policy(policy, l), expected);
+            }
+
+            let ip = solve_cov_instance(
+                3,
+                3,
+                l,
+                &bpr.positions,
+                &instance.realizations,
+                bpr.get_position_weights(),
+                bpr.group_constraint.as_ref(),
+            );
+            assert!(crate::is_close(ip, expected));
+        }
+    }
+
+    #[test]
+    fn batched_policies_with_k_rounds_are_adaptive() {
+        let rng = &mut Pcg64::seed_from_u64(24);
+        let bpr =
+            BipartiteRegulatorProbing::create_random(rng, 12, 8, 5, &DistributionFamily::UNIFORM)
+                .unwrap();
+
+        for (k, l) in [(1, 1), (4, 1), (4, 2), (6, 3), (12, 4)] {
+            for instance_index in 0..3 {
+                let instance = bpr.create_instance(instance_index);
+                assert_eq!(
+                    instance.batched_policy_regulators(k, l, k),
+                    instance.adaptive_policy_regulators(k, l)
+                );
+            }
+
+            for reduction in [Reduction::MAX, Reduction::SUM] {
+                let pm = ProbeMax::from_bpr(bpr.clone(), reduction);
+                for instance_index in 0..3 {
+                    let instance = pm.create_instance(instance_index);
+                    assert_eq!(
+                        instance.batched_policy_boxes(k, l, k),
+                        instance.adaptive_policy_boxes(k, l)
+                    );
+                }
+            }
+        }
+    }
+
+    /// Random model with `na` regulators on 6 positions and the given probing costs
+    fn random_model(seed: u64, na: usize, costs: Vec<f64>) -> BipartiteRegulatorProbing {
+        let rng = &mut Pcg64::seed_from_u64(seed);
+        let mut bpr =
+            BipartiteRegulatorProbing::create_random(rng, na, 6, 4, &DistributionFamily::UNIFORM)
+                .unwrap();
+        bpr.set_regulator_costs(costs);
+        bpr
+    }
+
+    /// Does the probing cost of `probed` stay within the budget (up to rounding errors) without repetitions
+    fn within_budget(bpr: &BipartiteRegulatorProbing, probed: &[usize], budget: f64) -> bool {
+        let cost = bpr.get_probing_cost(probed);
+        probed.iter().all_unique() && (cost <= budget || crate::is_close(cost, budget))
+    }
+
+    #[test]
+    fn budget_policies_stay_within_budget() {
+        let costs = vec![0.5, 1.0, 1.5, 2.0, 0.25, 3.0, 1.25, 0.75];
+        let bpr = random_model(19, 8, costs);
+        let pm_max = ProbeMax::from_bpr(bpr.clone(), Reduction::MAX);
+        let pm_sum = ProbeMax::from_bpr(bpr.clone(), Reduction::SUM);
+
+        for budget in [0.1, 0.25, 1.0, 2.6, 4.0, 7.5, 100.0] {
+            for l in 1..=3 {
+                assert!(within_budget(
+                    &bpr,
+                    &bpr.compute_namp_cov_budget_policy(budget, l),
+                    budget
+                ));
+                for instance_index in 0..3 {
+                    let instance = bpr.create_instance(instance_index);
+                    let amp = instance.adaptive_policy_regulators_budget(budget, l);
+                    assert!(within_budget(&bpr, &amp, budget));
+                }
+            }
+
+            for pm in [&pm_max, &pm_sum] {
+                assert!(within_budget(
+                    &bpr,
+                    &pm.compute_namp_budget_policy(budget),
+                    budget
+                ));
+                for instance_index in 0..3 {
+                    let instance = pm.create_instance(instance_index);
+                    let amp = instance.adaptive_policy_boxes_budget(budget, 2);
+                    assert!(within_budget(&bpr, &amp, budget));
+                }
+            }
+        }
+
+        // Costs of 0.1 sum up to slightly more than 0.3 but three regulators still fit (and not four)
+        let bpr = random_model(19, 5, vec![0.1; 5]);
+        let pm = ProbeMax::from_bpr(bpr.clone(), Reduction::MAX);
+        assert_eq!(bpr.compute_namp_cov_budget_policy(0.3, 2).len(), 3);
+        assert_eq!(
+            bpr.create_instance(0)
+                .adaptive_policy_regulators_budget(0.3, 2)
+                .len(),
+            3
+        );
+        assert_eq!(pm.compute_namp_budget_policy(0.3).len(), 3);
+        assert_eq!(
+            pm.create_instance(0)
+                .adaptive_policy_boxes_budget(0.3, 2)
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn budget_policies_prefer_cheap_regulators() {
+        // Two identical regulators where the second one is cheaper
+        let dist = WeightedDistribution::with_support(&[0.0, 1.0, 4.0], &[0.3, 0.5, 0.2]);
+        let edges = vec![vec![(0, dist.clone())], vec![(0, dist)]];
+        let mut bpr = BipartiteRegulatorProbing::new_sparse(2, 1, edges, 0);
+        bpr.set_regulator_costs(vec![2.0, 1.0]);
+        let pm = ProbeMax::from_bpr(bpr.clone(), Reduction::MAX);
+
+        for budget in [1.0, 2.0, 3.0] {
+            let expected = if budget < 3.0 { vec![1] } else { vec![1, 0] };
+            assert_eq!(bpr.compute_namp_cov_budget_policy(budget, 1), expected);
+            assert_eq!(pm.compute_namp_budget_policy(budget), expected);
+            for instance_index in 0..5 {
+                let instance = bpr.create_instance(instance_index);
+                assert_eq!(
+                    instance.adaptive_policy_regulators_budget(budget, 1),
+                    expected
+                );
+                let instance = pm.create_instance(instance_index);
+                assert_eq!(instance.adaptive_policy_boxes_budget(budget, 1), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn budget_policies_with_unit_costs_probe_k_regulators() {
+        let mut bpr = random_model(91, 10, vec![1.0; 10]);
+        for (k, l) in [(1, 1), (3, 1), (4, 2), (5, 3), (6, 6)] {
+            let budget = k as f64;
+
+            bpr.compute_namp_cov_policy(k, l);
+            assert_eq!(
+                &bpr.compute_namp_cov_budget_policy(budget, l),
+                bpr.get_policy(k, l).unwrap()
+            );
+            for instance_index in 0..3 {
+                let instance = bpr.create_instance(instance_index);
+                assert_eq!(
+                    instance.adaptive_policy_regulators_budget(budget, l),
+                    instance.adaptive_policy_regulators(k, l)
+                );
+            }
+
+            for reduction in [Reduction::MAX, Reduction::SUM] {
+                let pm = ProbeMax::from_bpr(bpr.clone(), reduction);
+                assert_eq!(pm.compute_namp_budget_policy(budget), pm.get_policy()[..k]);
+                for instance_index in 0..3 {
+                    let instance = pm.create_instance(instance_index);
+                    assert_eq!(
+                        instance.adaptive_policy_boxes_budget(budget, l),
+                        instance.adaptive_policy_boxes(k, l)
+                    );
+                }
+            }
+        }
+    }
+
+    /// Do the edges exist and are they pairwise distinct and at most `budget` many
+    fn valid_edges(bpr: &BipartiteRegulatorProbing, edges: &[Edge], budget: usize) -> bool {
+        edges.len() <= budget
+            && edges.iter().all_unique()
+            && edges.iter().all(|(a, b)| bpr.get_edge(*a, *b).is_some())
+    }
+
+    #[test]
+    fn edge_policies_probe_distinct_edges_within_budget() {
+        let rng = &mut Pcg64::seed_from_u64(23);
+        let edges: Vec<Vec<(usize, WeightedDistribution)>> = (0..6)
+            .map(|a| {
+                (0..5)
+                    .filter(|b| (a + b) % 3 != 0)
+                    .map(|b| {
+                        (
+                            b,
+                            create_random_distribution(rng, &DistributionFamily::UNIFORM, 4),
+                        )
+                    })
+                    .map(|(b, dist)| (b, dist.unwrap()))
+                    .collect()
+            })
+            .collect();
+        let bpr = BipartiteRegulatorProbing::new_sparse(6, 5, edges, 3);
+        let num_edges = bpr.get_num_edges();
+
+        for budget in [0, 1, 4, 10, num_edges, num_edges + 5] {
+            for l in 1..=3 {
+                let namp = bpr.compute_namp_edge_policy(budget, l);
+                assert!(valid_edges(&bpr, &namp, budget));
+                assert_eq!(namp.len(), budget.min(num_edges));
+
+                for instance_index in 0..3 {
+                    let amp = bpr
+                        .create_instance(instance_index)
+                        .adaptive_policy_edges(budget, l);
+                    assert!(valid_edges(&bpr, &amp, budget));
+                    assert_eq!(amp.len(), budget.min(num_edges));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn edge_selection_uses_only_revealed_edges() {
+        // Regulator 0 has two edges with expected value 5 that both realize to 0,
+        // Regulator 1 has a single edge which is always 4
+        let coin = WeightedDistribution::with_support(&[0.0, 10.0], &[0.5, 0.5]);
+        let edges = vec![
+            vec![(0, coin.clone()), (1, coin)],
+            vec![(2, WeightedDistribution::with_support(&[4.0], &[1.0]))],
+        ];
+        let mut bpr = BipartiteRegulatorProbing::new_sparse(2, 3, edges, 0);
+        bpr.stored_realizations = vec![vec![vec![0.0, 0.0], vec![4.0]]];
+        let instance = bpr.create_instance(0);
+
+        // Without probes or with one revealed edge, the unprobed edge counts with its expected value 5
+        assert_eq!(instance.select_by_edges(&[], 1), vec![0]);
+        assert_eq!(instance.select_by_edges(&[(0, 0)], 1), vec![0]);
+        assert_eq!(instance.select_by_edges(&[(0, 1)], 1), vec![0]);
+        // Only when both edges are revealed, the realizations of Regulator 0 are known
+        assert_eq!(instance.select_by_edges(&[(0, 0), (0, 1)], 1), vec![1]);
+        assert_eq!(
+            instance.select_by_edges(&[(1, 2), (0, 1), (0, 0)], 1),
+            vec![1]
+        );
+    }
+}
@@ -4,108 +4,334 @@
 //! It also provides a weighted distribution which extends the AliasTable to allow for computation with
 //! individual probabilities and expected values.
 
+use std::{
+    cmp::Ordering,
+    convert::TryFrom,
+    error::Error,
+    f64::consts::PI,
+    fmt::{self, Display, Formatter},
+};
+
+use num::complex::Complex64;
 use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
 
-use statrs::distribution::{Discrete, Poisson};
+use statrs::{
+    distribution::{Binomial, Discrete, NegativeBinomial, Poisson},
+    function::{beta::ln_beta, factorial::ln_binomial},
+};
 
-/// Extends `WeightedSampling` by also storing the individual probabilities and expected values
-/// to allow for computation with those as well as sampling.
+use crate::{is_close, Binning, DistributionFamily};
+
+/// Walkers AliasTable over the indices `0..n` of a list of probabilities.
+/// Construction takes linear time, every draw takes constant time.
 #[derive(Debug, Clone)]
+pub struct AliasTable {
+    prob: Vec<f64>,
+    alias: Vec<usize>,
+}
+
+impl AliasTable {
+    /// Creates an AliasTable from a list of probabilities summing up to `1.0` (using Vose's method)
+    pub fn new(probabilities: &[f64]) -> Self {
+        let n = probabilities.len();
+        assert!(n > 0);
+
+        let mut prob: Vec<f64> = probabilities.iter().map(|p| p * n as f64).collect();
+        let mut alias: Vec<usize> = (0..n).collect();
+
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) = (0..n).partition(|i| prob[*i] < 1.0);
+
+        while let (Some(s), Some(&l)) = (small.pop(), large.last()) {
+            alias[s] = l;
+            prob[l] -= 1.0 - prob[s];
+            if prob[l] < 1.0 {
+                large.pop();
+                small.push(l);
+            }
+        }
+
+        // Remaining entries are (up to rounding errors) exactly 1.0
+        for i in small.into_iter().chain(large) {
+            prob[i] = 1.0;
+        }
+
+        Self { prob, alias }
+    }
+
+    /// Returns the number of entries in the table
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.prob.len()
+    }
+
+    /// Draws a random index in constant time
+    #[inline]
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> usize {
+        let i = rng.random_range(0..self.prob.len());
+        if rng.random::<f64>() < self.prob[i] {
+            i
+        } else {
+            self.alias[i]
+        }
+    }
+}
+
+/// Neumaier's compensated summation to keep rounding errors of long sums independent of their length
+#[derive(Debug, Clone, Copy, Default)]
+struct CompensatedSum {
+    sum: f64,
+    compensation: f64,
+}
+
+impl CompensatedSum {
+    #[inline]
+    fn add(&mut self, x: f64) {
+        let t = self.sum + x;
+        // Infinite terms (e.g. `ln(0.0)`) would turn the compensation into NaN
+        if !t.is_finite() {
+            self.sum = t;
+            return;
+        }
+
+        if self.sum.abs() >= x.abs() {
+            self.compensation += (self.sum - t) + x;
+        } else {
+            self.compensation += (x - t) + self.sum;
+        }
+        self.sum = t;
+    }
+
+    #[inline]
+    fn value(&self) -> f64 {
+        self.sum + self.compensation
+    }
+}
+
+/// Extends `AliasTable` by also storing the individual probabilities and expected values
+/// to allow for computation with those as well as sampling.
+///
+/// The support is a strictly increasing list of real values `v_0 < ... < v_{n-1}`.
+/// Methods taking an index `i` refer to the support value `v_i`.
+///
+/// Only the support and the probabilities are serialized, everything else is rebuilt (and validated) on loading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "StoredDistribution", into = "StoredDistribution")]
 pub struct WeightedDistribution {
     n: usize,
+    /// Support values. `None` is the integer support `{0, ..., n - 1}` which is not stored explicitly.
+    support: Option<Vec<f64>>,
     cum_prob: Vec<f64>,
     cum_expe: Vec<f64>,
-    samples: Vec<usize>,
+    alias_table: AliasTable,
+    mass_error: f64,
+    second_moment: f64,
+    entropy: f64,
 }
 
-impl WeightedDistribution {
-    /// Creates a weighted distribution from a list of weights.
-    /// Allows for constant time
-    /// - Probabilities
-    /// - Expected Values
-    pub fn new<R: Rng>(rng: &mut R, weights: &[f64], num_samples: usize) -> Self {
-        let n = weights.len();
-        assert!(n > 0);
-
-        let total_weight = {
-            let mut sum = 0.0f64;
-            for w in weights {
-                sum += *w;
-            }
-            sum
-        };
+/// Serialized form of a `WeightedDistribution`
+#[derive(Serialize, Deserialize)]
+struct StoredDistribution {
+    /// Support values (omitted for the integer support `{0, ..., n - 1}`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    values: Option<Vec<f64>>,
+    probs: Vec<f64>,
+}
 
-        let exact_probabilities: Vec<f64> = weights.iter().map(|w| w / total_weight).collect();
+impl From<WeightedDistribution> for StoredDistribution {
+    fn from(dist: WeightedDistribution) -> Self {
+        Self {
+            probs: dist.get_probs(),
+            values: dist.support,
+        }
+    }
+}
 
-        let sample_probs: Vec<f64> = (0..num_samples)
-            .map(|_| rng.random_range(0.0..=1.0))
-            .collect();
-        let mut samples: Vec<usize> = vec![0; num_samples];
+impl TryFrom<StoredDistribution> for WeightedDistribution {
+    type Error = DistributionError;
 
-        let mut cum_prob = Vec::with_capacity(n);
-        let mut cum_expe = Vec::with_capacity(n);
+    fn try_from(stored: StoredDistribution) -> Result<Self, Self::Error> {
+        match stored.values {
+            Some(values) => Self::try_with_support(&values, &stored.probs),
+            None => Self::try_new(&stored.probs),
+        }
+    }
+}
 
-        cum_prob.push(exact_probabilities[0]);
-        cum_expe.push(0.0);
+/// Errors when creating a `WeightedDistribution` from invalid input
+#[derive(Debug, Clone, PartialEq)]
+pub enum DistributionError {
+    /// No weights were given
+    Empty,
+    /// Number of support values and weights differ
+    LengthMismatch { values: usize, weights: usize },
+    /// A weight is negative, NaN or infinite
+    InvalidWeight { index: usize, weight: f64 },
+    /// All weights are zero
+    ZeroTotalWeight,
+    /// A support value is NaN or infinite
+    InvalidValue { index: usize, value: f64 },
+    /// The support values are not strictly increasing at `index`
+    UnsortedSupport { index: usize },
+    /// A parameter of a distribution family is out of range
+    InvalidParameter { name: &'static str, value: f64 },
+}
 
-        for (i, p) in exact_probabilities.into_iter().enumerate() {
-            if i == 0 {
-                continue;
+impl Display for DistributionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "distribution has no weights"),
+            Self::LengthMismatch { values, weights } => write!(
+                f,
+                "distribution has {} support values but {} weights",
+                values, weights
+            ),
+            Self::InvalidWeight { index, weight } => {
+                write!(f, "weight {} at index {} is invalid", weight, index)
             }
+            Self::ZeroTotalWeight => write!(f, "all weights of the distribution are zero"),
+            Self::InvalidValue { index, value } => {
+                write!(f, "support value {} at index {} is invalid", value, index)
+            }
+            Self::UnsortedSupport { index } => {
+                write!(
+                    f,
+                    "support values are not strictly increasing at index {}",
+                    index
+                )
+            }
+            Self::InvalidParameter { name, value } => {
+                write!(f, "parameter {} = {} is out of range", name, value)
+            }
+        }
+    }
+}
 
-            cum_prob.push(cum_prob[i - 1] + p);
-            cum_expe.push(cum_expe[i - 1] + p * i as f64);
+impl Error for DistributionError {}
 
-            (0..num_samples).for_each(|j| {
-                if sample_probs[j] <= cum_prob[i] && sample_probs[j] >= cum_prob[i - 1] {
-                    samples[j] = i;
-                }
+impl WeightedDistribution {
+    /// Creates a weighted distribution over the support `{0, ..., n - 1}` from a list of `n` weights.
+    /// Panics on invalid weights, see `try_new`.
+    /// Allows for constant time
+    /// - Probabilities
+    /// - Expected Values
+    /// - Sampling
+    pub fn new(weights: &[f64]) -> Self {
+        Self::try_new(weights).unwrap()
+    }
+
+    /// Creates a weighted distribution over the support `{0, ..., n - 1}` from a list of `n` weights.
+    /// The weights are renormalized, tiny negative weights from rounding errors are clamped to `0.0`.
+    pub fn try_new(weights: &[f64]) -> Result<Self, DistributionError> {
+        Self::build(None, weights)
+    }
+
+    /// Creates a weighted distribution over an explicit support from a list of weights.
+    /// Panics on invalid input, see `try_with_support`.
+    pub fn with_support(values: &[f64], weights: &[f64]) -> Self {
+        Self::try_with_support(values, weights).unwrap()
+    }
+
+    /// Creates a weighted distribution over an explicit support from a list of weights.
+    /// `values` must be finite, strictly increasing and have the same length as `weights`.
+    pub fn try_with_support(values: &[f64], weights: &[f64]) -> Result<Self, DistributionError> {
+        if values.len() != weights.len() {
+            return Err(DistributionError::LengthMismatch {
+                values: values.len(),
+                weights: weights.len(),
             });
         }
 
-        Self {
-            n,
-            cum_prob,
-            cum_expe,
-            samples,
+        if let Some((index, value)) = values.iter().enumerate().find(|(_, v)| !v.is_finite()) {
+            return Err(DistributionError::InvalidValue {
+                index,
+                value: *value,
+            });
+        }
+
+        if let Some(index) = (1..values.len()).find(|i| values[i - 1] >= values[*i]) {
+            return Err(DistributionError::UnsortedSupport { index });
+        }
+
+        let is_integer_support = values.iter().enumerate().all(|(i, v)| *v == i as f64);
+        if is_integer_support {
+            Self::build(None, weights)
+        } else {
+            Self::build(Some(values.to_vec()), weights)
         }
     }
 
-    pub fn new_without_rng(weights: &[f64], samples: Vec<usize>) -> Self {
+    fn build(support: Option<Vec<f64>>, weights: &[f64]) -> Result<Self, DistributionError> {
         let n = weights.len();
-        assert!(n > 0);
+        if n == 0 {
+            return Err(DistributionError::Empty);
+        }
+
+        if let Some((index, weight)) = weights
+            .iter()
+            .enumerate()
+            .find(|(_, w)| !w.is_finite() || (**w < 0.0 && !is_close(**w, 0.0)))
+        {
+            return Err(DistributionError::InvalidWeight {
+                index,
+                weight: *weight,
+            });
+        }
+
+        let weights: Vec<f64> = weights.iter().map(|w| w.max(0.0)).collect();
 
         let total_weight = {
-            let mut sum = 0.0f64;
-            for w in weights {
-                sum += *w;
+            let mut sum = CompensatedSum::default();
+            for w in &weights {
+                sum.add(*w);
             }
-            sum
+            sum.value()
         };
 
+        if total_weight <= 0.0 {
+            return Err(DistributionError::ZeroTotalWeight);
+        }
+
         let exact_probabilities: Vec<f64> = weights.iter().map(|w| w / total_weight).collect();
 
         let mut cum_prob = Vec::with_capacity(n);
         let mut cum_expe = Vec::with_capacity(n);
 
-        cum_prob.push(exact_probabilities[0]);
-        cum_expe.push(0.0);
+        let mut prob_sum = CompensatedSum::default();
+        let mut expe_sum = CompensatedSum::default();
+        let mut seco_sum = CompensatedSum::default();
+        let mut entropy = CompensatedSum::default();
 
-        for (i, p) in exact_probabilities.into_iter().enumerate() {
-            if i == 0 {
-                continue;
+        for (i, p) in exact_probabilities.iter().enumerate() {
+            let value = support.as_ref().map_or(i as f64, |s| s[i]);
+
+            prob_sum.add(*p);
+            expe_sum.add(p * value);
+            seco_sum.add(p * value * value);
+            if *p > 0.0 {
+                entropy.add(-p * p.ln());
             }
 
-            cum_prob.push(cum_prob[i - 1] + p);
-            cum_expe.push(cum_expe[i - 1] + p * i as f64);
+            cum_prob.push(prob_sum.value().min(1.0));
+            cum_expe.push(expe_sum.value());
         }
 
-        Self {
+        Ok(Self {
             n,
+            support,
             cum_prob,
             cum_expe,
-            samples,
-        }
+            alias_table: AliasTable::new(&exact_probabilities),
+            mass_error: (total_weight - 1.0).abs(),
+            second_moment: seco_sum.value(),
+            entropy: entropy.value(),
+        })
+    }
+
+    /// Draws a fresh realization in constant time
+    #[inline]
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> f64 {
+        self.value(self.alias_table.sample(rng))
     }
 
     /// Returns the vector of probabilities
@@ -114,23 +340,61 @@ impl WeightedDistribution {
         (0..self.n).map(|i| self.prob_equal(i)).collect()
     }
 
+    /// Returns the vector of support values
+    #[inline]
+    pub fn get_values(&self) -> Vec<f64> {
+        (0..self.n).map(|i| self.value(i)).collect()
+    }
+
     /// Returns the size of the distribution
     #[inline]
     pub fn size(&self) -> usize {
         self.n
     }
 
+    /// Is the support `{0, ..., n - 1}`
+    #[inline]
+    pub fn has_integer_support(&self) -> bool {
+        self.support.is_none()
+    }
+
+    /// Returns `|1 - sum of weights|` of the weights this distribution was created from.
+    /// For derived distributions (max, sum) this is the total mass error before renormalization.
+    #[inline]
+    pub fn mass_error(&self) -> f64 {
+        self.mass_error
+    }
+
+    /// `v_i`
+    #[inline]
+    pub fn value(&self, i: usize) -> f64 {
+        match &self.support {
+            Some(s) => s[i],
+            None => i as f64,
+        }
+    }
+
+    /// Number of support values `v_i <= x`
     #[inline]
-    pub fn get_sample(&self, i: usize) -> usize {
-        self.samples[i]
+    pub fn count_at_most(&self, x: f64) -> usize {
+        match &self.support {
+            Some(s) => s.partition_point(|v| *v <= x),
+            None if x < 0.0 => 0,
+            None => (x.floor() as usize).saturating_add(1).min(self.n),
+        }
     }
 
+    /// Number of support values `v_i < x`
     #[inline]
-    pub fn num_samples(&self) -> usize {
-        self.samples.len()
+    pub fn count_less(&self, x: f64) -> usize {
+        match &self.support {
+            Some(s) => s.partition_point(|v| *v < x),
+            None if x <= 0.0 => 0,
+            None => (x.ceil() as usize).min(self.n),
+        }
     }
 
-    /// `P[X = i]`
+    /// `P[X = v_i]`
     #[inline]
     pub fn prob_equal(&self, i: usize) -> f64 {
         if i == 0 {
@@ -140,7 +404,7 @@ impl WeightedDistribution {
         }
     }
 
-    /// `P[X < i]`
+    /// `P[X < v_i]`
     #[inline]
     pub fn prob_less(&self, i: usize) -> f64 {
         if i == 0 {
@@ -150,7 +414,7 @@ impl WeightedDistribution {
         }
     }
 
-    /// `P[X > i]`
+    /// `P[X > v_i]`
     #[inline]
     pub fn prob_greater(&self, i: usize) -> f64 {
         if i == self.n - 1 {
@@ -160,33 +424,51 @@ impl WeightedDistribution {
         }
     }
 
-    /// `i * P[X = i]`
+    /// `P[X <= x]` for any real `x`
+    #[inline]
+    pub fn cdf(&self, x: f64) -> f64 {
+        self.prob_less(self.count_at_most(x))
+    }
+
+    /// `v_i * P[X = v_i]`
     #[inline]
     pub fn expected_equal(&self, i: usize) -> f64 {
         if i == 0 {
-            0.0
+            self.cum_expe[0]
         } else {
             self.cum_expe[i] - self.cum_expe[i - 1]
         }
     }
 
-    /// `E[X | X < i] * P[X < i]`
+    /// `E[X | X < v_i] * P[X < v_i]`
     #[inline]
     pub fn expected_less(&self, i: usize) -> f64 {
-        if i == 0 || i == 1 {
+        if i == 0 {
             0.0
         } else {
             self.cum_expe[i - 1]
         }
     }
 
-    /// `E[X | X > i] * P[X > i]`
+    /// `E[X | X > x] * P[X > x]` for any real `x`
     #[inline]
-    pub fn expected_greater(&self, i: usize) -> f64 {
-        if i == self.n - 1 {
+    pub fn expected_greater(&self, x: f64) -> f64 {
+        let i = self.count_at_most(x);
+        if i == self.n {
             0.0
         } else {
-            self.cum_expe[self.n - 1] - self.cum_expe[i]
+            self.cum_expe[self.n - 1] - self.expected_less(i)
+        }
+    }
+
+    /// `E[(X - x)^+]` for any real `x`, i.e. the expected increase of `max(X, x)` over `x`
+    #[inline]
+    pub fn expected_excess(&self, x: f64) -> f64 {
+        let i = self.count_at_most(x);
+        if i == self.n {
+            0.0
+        } else {
+            self.cum_expe[self.n - 1] - self.expected_less(i) - x * (1.0 - self.prob_less(i))
         }
     }
 
@@ -196,119 +478,1524 @@ impl WeightedDistribution {
         self.cum_expe[self.n - 1]
     }
 
+    /// `E[X^2]`
+    #[inline]
+    pub fn second_moment(&self) -> f64 {
+        self.second_moment
+    }
+
+    /// `Var[X]`
+    #[inline]
+    pub fn variance(&self) -> f64 {
+        let mean = self.expected_value();
+        (self.second_moment - mean * mean).max(0.0)
+    }
+
+    /// Standard deviation of `X`
+    #[inline]
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Shannon entropy of `X` (in nats)
+    #[inline]
+    pub fn entropy(&self) -> f64 {
+        self.entropy
+    }
+
+    /// Inverse CDF: the smallest support value `v_i` with `P[X <= v_i] >= q` for `q` in `[0, 1]`
+    #[inline]
+    pub fn quantile(&self, q: f64) -> f64 {
+        assert!((0.0..=1.0).contains(&q));
+        let i = self.cum_prob.partition_point(|p| *p < q).min(self.n - 1);
+        self.value(i)
+    }
+
+    /// `P[X >= t]` for any real `t`
+    #[inline]
+    pub fn prob_at_least(&self, t: f64) -> f64 {
+        1.0 - self.prob_less(self.count_less(t))
+    }
+
+    /// `E[X | X >= t] * P[X >= t]` for any real `t`
+    #[inline]
+    pub fn expected_at_least(&self, t: f64) -> f64 {
+        self.expected_value() - self.expected_less(self.count_less(t))
+    }
+
+    /// `E[X | X >= t]` for any real `t` or `None` if `P[X >= t] = 0`
+    #[inline]
+    pub fn conditional_expectation(&self, t: f64) -> Option<f64> {
+        let prob = self.prob_at_least(t);
+        (prob > 0.0).then(|| self.expected_at_least(t) / prob)
+    }
+
+    /// Posterior distribution of `X` given the observation `y` of `X + N(0, sigma^2)` for `sigma > 0`.
+    /// The weights are computed in log-space, so observations far from the support still yield a distribution.
+    pub fn gaussian_posterior(&self, y: f64, sigma: f64) -> Self {
+        assert!(sigma > 0.0);
+        let values = self.get_values();
+        let log_weights: Vec<f64> = values
+            .iter()
+            .zip(self.get_probs())
+            .map(|(v, p)| {
+                if p > 0.0 {
+                    p.ln() - 0.5 * ((y - v) / sigma).powi(2)
+                } else {
+                    f64::NEG_INFINITY
+                }
+            })
+            .collect();
+        let max = log_weights
+            .iter()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        let weights: Vec<f64> = log_weights.iter().map(|w| (w - max).exp()).collect();
+        Self::with_support(&values, &weights)
+    }
+
+    /// Maps the distribution onto at most `bins` support values (see `rebin_uniform` and `rebin_quantile`).
+    /// Returns the coarse distribution `Y` together with `E[|X - Y|]` under the coupling `Y = bin(X)`.
+    ///
+    /// The bound also holds for `|E[X] - E[Y]|` and for `E[(X - x)^+]`, and summing it over independent
+    /// variables bounds the change in the expected value of their maximum, sum and coverage.
+    pub fn rebin(&self, bins: usize, binning: &Binning) -> (Self, f64) {
+        assert!(bins > 0);
+
+        match binning {
+            // A single bin is the point mass at the expected value for both binnings
+            _ if bins == 1 => self.rebin_quantile(1),
+            Binning::UNIFORM => {
+                let (min, max) = (self.value(0), self.value(self.n - 1));
+                self.rebin_uniform(min, (max - min) / (bins - 1) as f64)
+            }
+            Binning::QUANTILE => self.rebin_quantile(bins),
+        }
+    }
+
+    /// Rounds every support value to the nearest point of the lattice `{origin + width * i}`.
+    /// Distributions rebinned onto the same lattice can be convolved. If `origin` is at most the smallest
+    /// support value, the support spans at most `bins` lattice points for `width >= (max - origin) / (bins - 1)`.
+    pub fn rebin_uniform(&self, origin: f64, width: f64) -> (Self, f64) {
+        if width <= 0.0 || !width.is_finite() {
+            return (self.clone(), 0.0);
+        }
+
+        let targets: Vec<f64> = (0..self.n)
+            .map(|i| origin + ((self.value(i) - origin) / width).round() * width)
+            .collect();
+        self.rebin_to(&targets)
+    }
+
+    /// Groups consecutive support values into at most `bins` bins of (about) equal probability,
+    /// each represented by its conditional expectation (thus `E[X]` is preserved).
+    pub fn rebin_quantile(&self, bins: usize) -> (Self, f64) {
+        assert!(bins > 0);
+
+        if self.n <= bins {
+            return (self.clone(), 0.0);
+        }
+
+        // Index of the last support value of every bin
+        let mut ends: Vec<usize> = Vec::with_capacity(bins);
+        for i in 0..self.n - 1 {
+            let threshold = (ends.len() + 1) as f64 / bins as f64;
+            if ends.len() + 1 < bins && self.cum_prob[i] >= threshold - f64::EPSILON {
+                ends.push(i);
+            }
+        }
+        ends.push(self.n - 1);
+
+        let mut targets: Vec<f64> = Vec::with_capacity(self.n);
+        let mut start = 0;
+        for end in ends {
+            let prob = self.cum_prob[end] - self.prob_less(start);
+            let target = if prob > 0.0 {
+                (self.cum_expe[end] - self.expected_less(start)) / prob
+            } else {
+                (self.value(start) + self.value(end)) / 2.0
+            };
+            // Rounding may push the mean slightly out of its bin
+            let target = target.clamp(self.value(start), self.value(end));
+
+            targets.extend(std::iter::repeat_n(target, end + 1 - start));
+            start = end + 1;
+        }
+
+        self.rebin_to(&targets)
+    }
+
+    /// Moves the mass of `v_i` to `targets[i]` and returns the new distribution and `E[|X - Y|]`
+    fn rebin_to(&self, targets: &[f64]) -> (Self, f64) {
+        let mut error = CompensatedSum::default();
+        for (i, target) in targets.iter().enumerate() {
+            error.add(self.prob_equal(i) * (self.value(i) - target).abs());
+        }
+
+        let atoms: Vec<(f64, f64)> = targets
+            .iter()
+            .enumerate()
+            .map(|(i, target)| (*target, self.prob_equal(i)))
+            .collect();
+        let (values, weights): (Vec<f64>, Vec<f64>) = merge_atoms(atoms).into_iter().unzip();
+
+        (Self::with_support(&values, &weights), error.value())
+    }
+
+    /// Sorted union of the supports of all distributions
+    fn union_support(dist: &[Self]) -> Vec<f64> {
+        if dist.iter().all(|d| d.has_integer_support()) {
+            let n = dist.iter().map(|d| d.size()).max().unwrap_or(1);
+            return (0..n).map(|i| i as f64).collect();
+        }
+
+        let mut values: Vec<f64> = dist.iter().flat_map(|d| d.get_values()).collect();
+        values.sort_by(|a, b| a.total_cmp(b));
+        values.dedup();
+        values
+    }
+
+    /// Support of the form `{offset + step * i}` as `(offset, step)`.
+    /// The step of a distribution with a single value is `None`.
+    fn lattice(&self) -> Option<(f64, Option<f64>)> {
+        match &self.support {
+            None => Some((0.0, Some(1.0))),
+            Some(s) if s.len() == 1 => Some((s[0], None)),
+            Some(s) => {
+                let step = s[1] - s[0];
+                s.windows(2)
+                    .all(|w| is_close((w[1] - w[0]) / step, 1.0))
+                    .then_some((s[0], Some(step)))
+            }
+        }
+    }
+
     pub fn max_distribution(dist: &[Self]) -> Self {
-        let n: usize = dist[0].size();
+        let values = Self::union_support(dist);
+        let n = values.len();
 
         if n <= 1 {
-            return Self {
-                n,
-                cum_prob: vec![1.0f64],
-                cum_expe: vec![0.0f64],
-                samples: vec![0],
-            };
+            return Self::with_support(&values, &[1.0]);
         }
 
-        let weights: Vec<f64> = (0..n)
+        // `log P[max <= v_i] = sum_d log P[X_d <= v_i]` to prevent underflow for many edges
+        let log_cdf: Vec<f64> = (0..n)
             .map(|i| {
-                let mut inc = 1.0;
-                let mut out = 1.0;
-                for d in dist {
-                    if i < n - 1 {
-                        inc *= d.prob_less(i + 1);
-                    }
-                    out *= d.prob_less(i);
+                if i == n - 1 {
+                    return 0.0;
                 }
 
-                inc - out
+                let mut sum = CompensatedSum::default();
+                for d in dist {
+                    sum.add(d.cdf(values[i]).ln());
+                }
+                sum.value().min(0.0)
             })
             .collect();
 
-        let samples: Vec<usize> = (0..dist[0].num_samples())
+        // `P[max = v_i] = P[max <= v_i] * (1 - P[max <= v_{i - 1}] / P[max <= v_i])`
+        let weights: Vec<f64> = (0..n)
             .map(|i| {
-                let mut max = 0usize;
-                for d in dist {
-                    if d.get_sample(i) > max {
-                        max = d.get_sample(i);
-                    }
+                let inc = log_cdf[i];
+                let out = if i == 0 {
+                    f64::NEG_INFINITY
+                } else {
+                    log_cdf[i - 1]
+                };
+
+                if inc == f64::NEG_INFINITY {
+                    0.0
+                } else {
+                    (inc.exp() * -(out - inc).exp_m1()).max(0.0)
                 }
-                max
             })
             .collect();
 
-        Self::new_without_rng(&weights, samples)
+        Self::with_support(&values, &weights)
     }
 
+    /// Distribution of the sum of independent random variables.
+    /// If all supports lie on lattices with a common step, the probabilities are convolved.
+    /// Otherwise all pairwise sums of support values are enumerated which can be expensive.
     pub fn sum_distribution(dist: &[Self]) -> Self {
-        fn two_sum(a: &[f64], b: &[f64]) -> Vec<f64> {
-            let n = a.len() + b.len() - 1;
-            (0..n)
-                .map(|i| {
-                    (0..a.len().min(i + 1))
-                        .map(|j| {
-                            if i - j < b.len() {
-                                a[j] * b[i - j]
-                            } else {
-                                0.0
-                            }
-                        })
-                        .sum()
-                })
-                .collect()
-        }
-
-        let mut all_weights: Vec<Vec<f64>> = dist.iter().map(|d| d.get_probs()).collect();
-
-        while all_weights.len() > 1 {
-            let mut new_weights: Vec<Vec<f64>> = Vec::with_capacity(all_weights.len());
-            while all_weights.len() > 1 {
-                let a = all_weights.pop().unwrap();
-                let b = all_weights.pop().unwrap();
-                new_weights.push(two_sum(&a, &b));
-            }
-            if let Some(w) = all_weights.pop() {
-                new_weights.push(w);
+        let lattices: Option<Vec<(f64, Option<f64>)>> = dist.iter().map(|d| d.lattice()).collect();
+
+        if let Some(lattices) = lattices {
+            let mut steps = lattices.iter().filter_map(|(_, s)| *s);
+            let step = steps.next().unwrap_or(1.0);
+
+            if steps.all(|s| is_close(s / step, 1.0)) {
+                let offset: f64 = lattices.iter().map(|(o, _)| o).sum();
+                let weights =
+                    reduce_pairwise(dist.iter().map(|d| d.get_probs()).collect(), |a, b| {
+                        convolve(&a, &b)
+                    });
+                let values: Vec<f64> = (0..weights.len())
+                    .map(|i| offset + step * i as f64)
+                    .collect();
+
+                return Self::with_support(&values, &weights);
             }
-            all_weights = new_weights;
         }
 
-        let samples: Vec<usize> = (0..dist[0].num_samples())
+        let atoms: Vec<(f64, f64)> = reduce_pairwise(
+            dist.iter()
+                .map(|d| d.get_values().into_iter().zip(d.get_probs()).collect())
+                .collect(),
+            |a, b| sum_atoms(&a, &b),
+        );
+        let (values, weights): (Vec<f64>, Vec<f64>) = atoms.into_iter().unzip();
+
+        Self::with_support(&values, &weights)
+    }
+
+    /// Distribution of the minimum of independent random variables
+    pub fn min_distribution(dist: &[Self]) -> Self {
+        let values = Self::union_support(dist);
+        let n = values.len();
+
+        if n <= 1 {
+            return Self::with_support(&values, &[1.0]);
+        }
+
+        // `log P[min >= v_i] = sum_d log P[X_d >= v_i]` analogous to `max_distribution`
+        let log_survival: Vec<f64> = (0..n)
             .map(|i| {
-                let mut sum = 0usize;
+                if i == 0 {
+                    return 0.0;
+                }
+
+                let mut sum = CompensatedSum::default();
                 for d in dist {
-                    sum += d.get_sample(i);
+                    sum.add(d.prob_at_least(values[i]).ln());
+                }
+                sum.value().min(0.0)
+            })
+            .collect();
+
+        // `P[min = v_i] = P[min >= v_i] * (1 - P[min >= v_{i + 1}] / P[min >= v_i])`
+        let weights: Vec<f64> = (0..n)
+            .map(|i| {
+                let inc = log_survival[i];
+                let out = if i == n - 1 {
+                    f64::NEG_INFINITY
+                } else {
+                    log_survival[i + 1]
+                };
+
+                if inc == f64::NEG_INFINITY {
+                    0.0
+                } else {
+                    (inc.exp() * -(out - inc).exp_m1()).max(0.0)
                 }
-                sum
             })
             .collect();
 
-        Self::new_without_rng(&all_weights[0], samples)
+        Self::with_support(&values, &weights)
     }
-}
 
-/// Creates a vector of random weights between 0.0 and 1.0
-#[inline]
-pub fn create_random_weights<R: Rng>(rng: &mut R, n: usize) -> Vec<f64> {
-    (0..n).map(|_| rng.random_range(0.1..=10.0)).collect()
-}
+    /// Distribution of the `j`-th largest of independent random variables (`j = 1` is the maximum),
+    /// which is always 0 for less than `j` variables. Takes `O(|support| * |dist| * j)` time.
+    pub fn order_statistic_distribution(dist: &[Self], j: usize) -> Self {
+        assert!(j >= 1, "ORDER(j) needs j >= 1");
+        if j > dist.len() {
+            return Self::new(&[1.0]);
+        }
 
-/// Creates a list of weights mirroring a Poisson-Distribution up to a fixed length `n`.
-#[inline]
-pub fn create_poisson_weights<R: Rng>(rng: &mut R, n: usize) -> Vec<f64> {
-    let lambda = rng.random_range(0.5..=2.5);
-    let poisson = Poisson::new(lambda).unwrap();
+        if j == 1 {
+            return Self::max_distribution(dist);
+        }
+        if j == dist.len() {
+            return Self::min_distribution(dist);
+        }
 
-    let mut total_weight = 0.0;
+        let values = Self::union_support(dist);
+        let n = values.len();
 
-    let mut weights: Vec<f64> = (0..(n - 1))
-        .map(|i| {
-            let p = poisson.pmf(i as u64);
-            total_weight += p;
-            p
-        })
-        .collect();
+        // `P[X_(j) <= v_i]` is the probability that less than `j` variables exceed `v_i`
+        let cdf: Vec<f64> = (0..n)
+            .map(|i| {
+                if i == n - 1 {
+                    return 1.0;
+                }
 
-    assert!(total_weight <= 1.0);
-    weights.push(1.0 - total_weight);
+                // `count[c] = P[exactly c variables seen so far exceed v_i]` for `c < j`
+                let mut count = vec![0.0; j];
+                count[0] = 1.0;
+                for d in dist {
+                    let q = 1.0 - d.cdf(values[i]);
+                    for c in (0..j).rev() {
+                        count[c] *= 1.0 - q;
+                        if c > 0 {
+                            count[c] += count[c - 1] * q;
+                        }
+                    }
+                }
 
-    weights
+                let mut sum = CompensatedSum::default();
+                for p in count {
+                    sum.add(p);
+                }
+                sum.value().clamp(0.0, 1.0)
+            })
+            .collect();
+
+        let weights: Vec<f64> = (0..n)
+            .map(|i| {
+                if i == 0 {
+                    cdf[0]
+                } else {
+                    (cdf[i] - cdf[i - 1]).max(0.0)
+                }
+            })
+            .collect();
+
+        Self::with_support(&values, &weights)
+    }
+
+    /// Distribution of the sum of the `t` largest of independent random variables.
+    ///
+    /// Conditions on the value `u` of the `t`-th largest variable: the result is `u * (t - c)` plus
+    /// the sum of the `c < t` variables exceeding `u`, which is tracked for every `u` in the union support.
+    /// Takes `O(|support| * |dist| * t^2 * A)` time where `A` is the number of distinct partial sums.
+    pub fn top_sum_distribution(dist: &[Self], t: usize) -> Self {
+        assert!(t >= 1);
+
+        if t >= dist.len() {
+            return Self::sum_distribution(dist);
+        }
+        if t == 1 {
+            return Self::max_distribution(dist);
+        }
+
+        let mut atoms: Vec<(f64, f64)> = Vec::new();
+        for u in Self::union_support(dist) {
+            // `states[c][e]`: partial sums of the `c` variables above `u` with `e` (capped at `t`) equal to `u`
+            let mut states: Vec<Vec<Vec<(f64, f64)>>> = vec![vec![Vec::new(); t + 1]; t];
+            states[0][0].push((0.0, 1.0));
+
+            for d in dist {
+                let below = d.prob_less(d.count_less(u));
+                let equal = d.prob_at_least(u) - (1.0 - d.cdf(u));
+                let above: Vec<(f64, f64)> = (d.count_at_most(u)..d.size())
+                    .map(|i| (d.value(i), d.prob_equal(i)))
+                    .collect();
+
+                let mut next: Vec<Vec<Vec<(f64, f64)>>> = vec![vec![Vec::new(); t + 1]; t];
+                for (c, row) in states.into_iter().enumerate() {
+                    for (e, sums) in row.into_iter().enumerate() {
+                        if sums.is_empty() {
+                            continue;
+                        }
+
+                        if below > 0.0 {
+                            next[c][e].extend(sums.iter().map(|(s, p)| (*s, p * below)));
+                        }
+                        if equal > 0.0 {
+                            next[c][(e + 1).min(t)]
+                                .extend(sums.iter().map(|(s, p)| (*s, p * equal)));
+                        }
+                        if c + 1 < t && !above.is_empty() {
+                            next[c + 1][e].extend(sum_atoms(&sums, &above));
+                        }
+                    }
+                }
+
+                states = next
+                    .into_iter()
+                    .map(|row| row.into_iter().map(merge_atoms).collect())
+                    .collect();
+            }
+
+            for (c, row) in states.into_iter().enumerate() {
+                for (e, sums) in row.into_iter().enumerate() {
+                    if c + e >= t {
+                        let offset = u * (t - c) as f64;
+                        atoms.extend(sums.into_iter().map(|(s, p)| (s + offset, p)));
+                    }
+                }
+            }
+        }
+
+        let (values, weights): (Vec<f64>, Vec<f64>) = merge_atoms(atoms).into_iter().unzip();
+        Self::with_support(&values, &weights)
+    }
+
+    /// Mixture of distributions where `dist[i]` is chosen with probability proportional to `weights[i]`
+    pub fn mixture_distribution(dist: &[Self], weights: &[f64]) -> Result<Self, DistributionError> {
+        if dist.len() != weights.len() {
+            return Err(DistributionError::LengthMismatch {
+                values: dist.len(),
+                weights: weights.len(),
+            });
+        }
+        let mixing = Self::try_new(weights)?.get_probs();
+
+        let values = Self::union_support(dist);
+        let probs: Vec<f64> = values
+            .iter()
+            .map(|v| {
+                let mut sum = CompensatedSum::default();
+                for (d, w) in dist.iter().zip(&mixing) {
+                    let i = d.count_less(*v);
+                    if i < d.size() && d.value(i) == *v {
+                        sum.add(w * d.prob_equal(i));
+                    }
+                }
+                sum.value()
+            })
+            .collect();
+
+        Self::try_with_support(&values, &probs)
+    }
+}
+
+/// How the edge weights of a regulator are combined into a single value.
+/// Every reduction can be applied to distributions and to realizations alike.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Reduction {
+    /// Largest edge
+    MAX,
+    /// Sum of all edges
+    SUM,
+    /// Smallest edge
+    MIN,
+    /// `j`-th largest edge (`j >= 1`, see `Reduction::order`) or 0 if there are less than `j` edges
+    ORDER(usize),
+    /// Sum of the `t` largest edges
+    TOP(usize),
+}
+
+impl Reduction {
+    /// Reduction to the `j`-th largest edge
+    pub fn order(j: usize) -> Result<Self, DistributionError> {
+        if j == 0 {
+            return Err(invalid_parameter("j", j as f64));
+        }
+        Ok(Reduction::ORDER(j))
+    }
+
+    /// Distribution of the reduction of independent random variables
+    pub fn distribution(&self, dist: &[WeightedDistribution]) -> WeightedDistribution {
+        match *self {
+            Reduction::MAX => WeightedDistribution::max_distribution(dist),
+            Reduction::SUM => WeightedDistribution::sum_distribution(dist),
+            Reduction::MIN => WeightedDistribution::min_distribution(dist),
+            Reduction::ORDER(j) => WeightedDistribution::order_statistic_distribution(dist, j),
+            Reduction::TOP(t) => WeightedDistribution::top_sum_distribution(dist, t),
+        }
+    }
+
+    /// Number of the `absent` zero-edges of a sparse Regulator that are needed to reduce it exactly:
+    /// more zeros than the largest `j` (or `t`) values never change the reduction
+    pub fn relevant_zeros(&self, absent: usize) -> usize {
+        absent.min(match *self {
+            Reduction::MAX | Reduction::SUM | Reduction::MIN => 1,
+            Reduction::ORDER(j) => j,
+            Reduction::TOP(t) => t,
+        })
+    }
+
+    /// Reduction of realizations
+    pub fn reduce(&self, mut values: Vec<f64>) -> f64 {
+        match *self {
+            Reduction::MAX => values.into_iter().reduce(f64::max).unwrap_or(0.0),
+            Reduction::SUM => values.into_iter().sum(),
+            Reduction::MIN => values.into_iter().reduce(f64::min).unwrap_or(0.0),
+            Reduction::ORDER(j) => {
+                assert!(j >= 1, "ORDER(j) needs j >= 1");
+                values.sort_by(|a, b| b.total_cmp(a));
+                values.get(j - 1).copied().unwrap_or(0.0)
+            }
+            Reduction::TOP(t) => {
+                values.sort_by(|a, b| b.total_cmp(a));
+                values.into_iter().take(t).sum()
+            }
+        }
+    }
+}
+
+/// Combines neighbouring elements pairwise until only one is left
+fn reduce_pairwise<T, F: Fn(T, T) -> T>(mut items: Vec<T>, combine: F) -> T {
+    assert!(!items.is_empty());
+
+    while items.len() > 1 {
+        let mut new_items: Vec<T> = Vec::with_capacity(items.len());
+        while items.len() > 1 {
+            let a = items.pop().unwrap();
+            let b = items.pop().unwrap();
+            new_items.push(combine(a, b));
+        }
+        if let Some(w) = items.pop() {
+            new_items.push(w);
+        }
+        items = new_items;
+    }
+
+    items.pop().unwrap()
+}
+
+/// Distribution of the sum of two independent random variables given as `(value, probability)` pairs.
+/// Sums that are close to each other are merged into a single value.
+fn sum_atoms(a: &[(f64, f64)], b: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let atoms: Vec<(f64, f64)> = a
+        .iter()
+        .flat_map(|(x, p)| b.iter().map(move |(y, q)| (x + y, p * q)))
+        .collect();
+    merge_atoms(atoms)
+}
+
+/// Sorts `(value, probability)` pairs by value and merges values that are close to each other
+fn merge_atoms(mut atoms: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+    atoms.sort_by(|(x, _), (y, _)| x.total_cmp(y));
+
+    let mut merged: Vec<(f64, f64)> = Vec::with_capacity(atoms.len());
+    for (x, p) in atoms {
+        match merged.last_mut() {
+            Some((y, q)) if is_close(*y, x) => *q += p,
+            _ => merged.push((x, p)),
+        }
+    }
+
+    merged
+}
+
+/// Minimum length of both inputs for which `convolve` uses the FFT instead of the exact convolution
+pub const FFT_THRESHOLD: usize = 128;
+
+/// Maximum absolute deviation of `convolve_fft` from `convolve_exact` for probability vectors
+/// (up to a support of `2^20` values)
+pub const FFT_TOLERANCE: f64 = 1e-12;
+
+/// Convolution of two probability vectors, i.e. the probabilities of the sum of two independent random variables.
+/// Chooses the FFT for large inputs and the exact quadratic computation otherwise.
+#[inline]
+pub fn convolve(a: &[f64], b: &[f64]) -> Vec<f64> {
+    if a.len().min(b.len()) >= FFT_THRESHOLD {
+        convolve_fft(a, b)
+    } else {
+        convolve_exact(a, b)
+    }
+}
+
+/// Exact convolution of two probability vectors in time `O(|a| * |b|)`
+pub fn convolve_exact(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let n = a.len() + b.len() - 1;
+    (0..n)
+        .map(|i| {
+            let mut sum = CompensatedSum::default();
+            for j in i.saturating_sub(b.len() - 1)..a.len().min(i + 1) {
+                sum.add(a[j] * b[i - j]);
+            }
+            sum.value()
+        })
+        .collect()
+}
+
+/// Convolution of two probability vectors using the FFT in time `O((|a| + |b|) * log(|a| + |b|))`.
+/// Rounding errors can produce tiny negative values which are clamped to `0.0`.
+pub fn convolve_fft(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let n = a.len() + b.len() - 1;
+    let size = n.next_power_of_two();
+
+    // Pack both inputs into a single complex vector: a as real and b as imaginary part
+    let mut values: Vec<Complex64> = (0..size)
+        .map(|i| {
+            Complex64::new(
+                a.get(i).copied().unwrap_or(0.0),
+                b.get(i).copied().unwrap_or(0.0),
+            )
+        })
+        .collect();
+
+    fft(&mut values, false);
+
+    // (a + ib)^2 = a^2 - b^2 + 2iab, so the product of both transforms is hidden in the imaginary part
+    for v in values.iter_mut() {
+        *v = *v * *v;
+    }
+
+    fft(&mut values, true);
+
+    values
+        .into_iter()
+        .take(n)
+        .map(|v| (v.im / 2.0).max(0.0))
+        .collect()
+}
+
+/// In-place iterative radix-2 FFT. The length of `values` must be a power of two.
+fn fft(values: &mut [Complex64], invert: bool) {
+    let n = values.len();
+    debug_assert!(n.is_power_of_two());
+
+    // Bit-Reversal Permutation
+    let mut j = 0usize;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j ^= bit;
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = 2.0 * PI / len as f64 * if invert { -1.0 } else { 1.0 };
+        let half = len / 2;
+        // Twiddle factors are computed directly to avoid accumulating rounding errors
+        let roots: Vec<Complex64> = (0..half)
+            .map(|k| Complex64::from_polar(1.0, angle * k as f64))
+            .collect();
+
+        for chunk in values.chunks_mut(len) {
+            let (lo, hi) = chunk.split_at_mut(half);
+            for k in 0..half {
+                let u = lo[k];
+                let v = hi[k] * roots[k];
+                lo[k] = u + v;
+                hi[k] = u - v;
+            }
+        }
+
+        len <<= 1;
+    }
+
+    if invert {
+        let scale = 1.0 / n as f64;
+        for v in values.iter_mut() {
+            *v *= scale;
+        }
+    }
+}
+
+/// Named families of distributions.
+/// Families with an unbounded support are truncated to `{0, ..., vs - 1}` where the tail mass is put on `vs - 1`.
+impl WeightedDistribution {
+    /// Binomial distribution `Bin(n, p)` over `{0, ..., n}`.
+    /// Trailing values with zero probability (e.g. for `p = 0`) are dropped.
+    pub fn binomial(n: u64, p: f64) -> Result<Self, DistributionError> {
+        let binom = Binomial::new(p, n).map_err(|_| invalid_parameter("p", p))?;
+        Self::try_new(&trim_zero_tail((0..=n).map(|i| binom.pmf(i)).collect()))
+    }
+
+    /// Beta-Binomial distribution `BetaBin(n, alpha, beta)` over `{0, ..., n}`
+    pub fn beta_binomial(n: u64, alpha: f64, beta: f64) -> Result<Self, DistributionError> {
+        if !(alpha > 0.0 && alpha.is_finite()) {
+            return Err(invalid_parameter("alpha", alpha));
+        }
+        if !(beta > 0.0 && beta.is_finite()) {
+            return Err(invalid_parameter("beta", beta));
+        }
+
+        let norm = ln_beta(alpha, beta);
+        let weights: Vec<f64> = (0..=n)
+            .map(|k| {
+                (ln_binomial(n, k) + ln_beta(k as f64 + alpha, (n - k) as f64 + beta) - norm).exp()
+            })
+            .collect();
+
+        Self::try_new(&trim_zero_tail(weights))
+    }
+
+    /// Poisson distribution `Poi(lambda)` truncated to `{0, ..., vs - 1}`
+    pub fn poisson(lambda: f64, vs: usize) -> Result<Self, DistributionError> {
+        if vs == 0 {
+            return Err(DistributionError::Empty);
+        }
+        if lambda == 0.0 {
+            return Self::try_new(&[1.0]);
+        }
+
+        let poisson = Poisson::new(lambda).map_err(|_| invalid_parameter("lambda", lambda))?;
+        Self::try_new(&truncated_weights(vs, |i| poisson.pmf(i)))
+    }
+
+    /// Geometric distribution (number of failures before the first success with probability `p`)
+    /// truncated to `{0, ..., vs - 1}`
+    pub fn geometric(p: f64, vs: usize) -> Result<Self, DistributionError> {
+        Self::negative_binomial(1.0, p, vs)
+    }
+
+    /// Negative-Binomial distribution (number of failures before the `r`-th success with probability `p`)
+    /// truncated to `{0, ..., vs - 1}`
+    pub fn negative_binomial(r: f64, p: f64, vs: usize) -> Result<Self, DistributionError> {
+        if vs == 0 {
+            return Err(DistributionError::Empty);
+        }
+        if !(r > 0.0 && r.is_finite()) {
+            return Err(invalid_parameter("r", r));
+        }
+        if !(p > 0.0 && p <= 1.0) {
+            return Err(invalid_parameter("p", p));
+        }
+
+        // The pmf of statrs is NaN for the degenerate case
+        if p == 1.0 {
+            return Self::try_new(&[1.0]);
+        }
+
+        let neg_binom = NegativeBinomial::new(r, p).map_err(|_| invalid_parameter("p", p))?;
+        Self::try_new(&truncated_weights(vs, |i| neg_binom.pmf(i)))
+    }
+
+    /// Bernoulli distribution scaled to `{0, value}`, i.e. `P[X = value] = p`
+    pub fn bernoulli_scaled(p: f64, value: f64) -> Result<Self, DistributionError> {
+        if !(0.0..=1.0).contains(&p) {
+            return Err(invalid_parameter("p", p));
+        }
+
+        match value.partial_cmp(&0.0) {
+            Some(Ordering::Greater) => Self::try_with_support(&[0.0, value], &[1.0 - p, p]),
+            Some(Ordering::Less) => Self::try_with_support(&[value, 0.0], &[p, 1.0 - p]),
+            Some(Ordering::Equal) => Self::try_new(&[1.0]),
+            None => Err(invalid_parameter("value", value)),
+        }
+    }
+
+    /// Empirical distribution of a list of samples
+    pub fn empirical(samples: &[f64]) -> Result<Self, DistributionError> {
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let mut values: Vec<f64> = Vec::new();
+        let mut weights: Vec<f64> = Vec::new();
+        for x in sorted {
+            if values.last() == Some(&x) {
+                *weights.last_mut().unwrap() += 1.0;
+            } else {
+                values.push(x);
+                weights.push(1.0);
+            }
+        }
+
+        if values.is_empty() {
+            return Err(DistributionError::Empty);
+        }
+
+        Self::try_with_support(&values, &weights)
+    }
+}
+
+#[inline]
+fn invalid_parameter(name: &'static str, value: f64) -> DistributionError {
+    DistributionError::InvalidParameter { name, value }
+}
+
+/// Weights of the first `vs - 1` values of a probability mass function with the remaining tail mass on `vs - 1`
+fn truncated_weights<F: Fn(u64) -> f64>(vs: usize, pmf: F) -> Vec<f64> {
+    let mut total_weight = CompensatedSum::default();
+
+    let mut weights: Vec<f64> = (0..(vs.max(1) - 1))
+        .map(|i| {
+            let p = pmf(i as u64);
+            total_weight.add(p);
+            p
+        })
+        .collect();
+
+    // Rounding errors may push the total weight slightly above 1.0
+    weights.push((1.0 - total_weight.value()).max(0.0));
+
+    weights
+}
+
+/// Drops trailing zero weights (keeping at least one)
+fn trim_zero_tail(mut weights: Vec<f64>) -> Vec<f64> {
+    while weights.len() > 1 && *weights.last().unwrap() == 0.0 {
+        weights.pop();
+    }
+    weights
+}
+
+/// Creates a random distribution of the given family with support size (at most) `vs`
+pub fn create_random_distribution<R: Rng>(
+    rng: &mut R,
+    family: &DistributionFamily,
+    vs: usize,
+) -> Result<WeightedDistribution, DistributionError> {
+    if vs == 0 {
+        return Err(DistributionError::Empty);
+    }
+
+    match family {
+        DistributionFamily::UNIFORM => {
+            WeightedDistribution::try_new(&create_random_weights(rng, vs))
+        }
+        DistributionFamily::POISSON => {
+            WeightedDistribution::try_new(&create_poisson_weights(rng, vs))
+        }
+        DistributionFamily::BINOMIAL => {
+            WeightedDistribution::binomial(vs as u64 - 1, rng.random_range(0.05..=0.5))
+        }
+        DistributionFamily::BETABINOMIAL => WeightedDistribution::beta_binomial(
+            vs as u64 - 1,
+            rng.random_range(0.5..=2.0),
+            rng.random_range(1.0..=5.0),
+        ),
+        DistributionFamily::GEOMETRIC => {
+            WeightedDistribution::geometric(rng.random_range(0.3..=0.9), vs)
+        }
+        DistributionFamily::NEGBINOMIAL => WeightedDistribution::negative_binomial(
+            rng.random_range(1.0..=5.0),
+            rng.random_range(0.4..=0.9),
+            vs,
+        ),
+        DistributionFamily::BERNOULLI => {
+            WeightedDistribution::bernoulli_scaled(rng.random_range(0.05..=0.5), (vs - 1) as f64)
+        }
+        DistributionFamily::EMPIRICAL => {
+            let samples: Vec<f64> = (0..vs).map(|_| rng.random_range(0..vs) as f64).collect();
+            WeightedDistribution::empirical(&samples)
+        }
+    }
+}
+
+/// Creates a vector of random weights between 0.0 and 1.0
+#[inline]
+pub fn create_random_weights<R: Rng>(rng: &mut R, n: usize) -> Vec<f64> {
+    (0..n).map(|_| rng.random_range(0.1..=10.0)).collect()
+}
+
+/// Creates a list of weights mirroring a Poisson-Distribution up to a fixed length `n`.
+#[inline]
+pub fn create_poisson_weights<R: Rng>(rng: &mut R, n: usize) -> Vec<f64> {
+    let lambda = rng.random_range(0.5..=2.5);
+    let poisson = Poisson::new(lambda).unwrap();
+
+    truncated_weights(n, |i| poisson.pmf(i))
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64;
+    use statrs::distribution::{ChiSquared, ContinuousCDF};
+
+    use super::*;
+    use crate::model::BipartiteRegulatorProbing;
+
+    /// Random probability vector of length `n`
+    fn random_probs(rng: &mut Pcg64, n: usize) -> Vec<f64> {
+        let weights = create_random_weights(rng, n);
+        let total: f64 = weights.iter().sum();
+        weights.into_iter().map(|w| w / total).collect()
+    }
+
+    /// Largest absolute difference of two probability vectors (missing entries are 0)
+    fn max_deviation(a: &[f64], b: &[f64]) -> f64 {
+        (0..a.len().max(b.len()))
+            .map(|i| (a.get(i).unwrap_or(&0.0) - b.get(i).unwrap_or(&0.0)).abs())
+            .fold(0.0, f64::max)
+    }
+
+    /// Draw `10^6` indices from an AliasTable over `probabilities` and check the frequencies with a chi-square test.
+    /// Indices with probability 0 must never be drawn.
+    fn assert_alias_frequencies(probabilities: &[f64], seed: u64) {
+        const DRAWS: usize = 1_000_000;
+
+        let table = AliasTable::new(probabilities);
+        assert_eq!(table.size(), probabilities.len());
+
+        let rng = &mut Pcg64::seed_from_u64(seed);
+        let mut counts = vec![0usize; probabilities.len()];
+        for _ in 0..DRAWS {
+            counts[table.sample(rng)] += 1;
+        }
+
+        let mut chi_square = 0.0;
+        let mut categories = 0;
+        for (&p, &count) in probabilities.iter().zip(&counts) {
+            if p == 0.0 {
+                assert_eq!(count, 0, "Drew an index with probability 0");
+                continue;
+            }
+            let expected = p * DRAWS as f64;
+            chi_square += (count as f64 - expected).powi(2) / expected;
+            categories += 1;
+        }
+
+        if categories > 1 {
+            let dist = ChiSquared::new((categories - 1) as f64).unwrap();
+            let p_value = 1.0 - dist.cdf(chi_square);
+            assert!(p_value > 1e-4, "Chi-square test failed: p = {}", p_value);
+        }
+    }
+
+    #[test]
+    fn alias_table_frequencies() {
+        let rng = &mut Pcg64::seed_from_u64(1);
+        for (seed, &n) in [2, 10, 100].iter().enumerate() {
+            assert_alias_frequencies(&random_probs(rng, n), seed as u64);
+        }
+
+        // Single value
+        assert_alias_frequencies(&[1.0], 3);
+
+        // Zero weights are never drawn
+        assert_alias_frequencies(&[0.0, 0.5, 0.0, 0.5, 0.0], 4);
+        assert_alias_frequencies(&[0.0, 0.0, 1.0], 5);
+        let mut probabilities = random_probs(rng, 20);
+        for p in probabilities.iter_mut().step_by(3) {
+            *p = 0.0;
+        }
+        let total: f64 = probabilities.iter().sum();
+        probabilities.iter_mut().for_each(|p| *p /= total);
+        assert_alias_frequencies(&probabilities, 6);
+
+        // One dominant weight
+        let mut probabilities = vec![1e-3; 11];
+        probabilities[7] = 0.99;
+        assert_alias_frequencies(&probabilities, 7);
+    }
+
+    #[test]
+    fn fft_convolution_matches_exact() {
+        let rng = &mut Pcg64::seed_from_u64(4);
+        for (n, m) in [
+            (FFT_THRESHOLD, FFT_THRESHOLD),
+            (FFT_THRESHOLD + 1, 3 * FFT_THRESHOLD),
+            (1000, 1500),
+            (2048, 2048),
+        ] {
+            let (a, b) = (random_probs(rng, n), random_probs(rng, m));
+            let deviation = max_deviation(&convolve_fft(&a, &b), &convolve_exact(&a, &b));
+            assert!(deviation <= FFT_TOLERANCE, "{} x {}: {}", n, m, deviation);
+        }
+    }
+
+    #[test]
+    fn fft_convolution_matches_exact_on_full_support() {
+        // Supports of `2^20` values in total: the exact convolution is only computed at sampled indices
+        let rng = &mut Pcg64::seed_from_u64(20);
+        for (n, m) in [(1 << 19, 1 << 19), ((1 << 20) - 1000, 1000)] {
+            let (a, b) = (random_probs(rng, n), random_probs(rng, m));
+            let fast = convolve_fft(&a, &b);
+            assert_eq!(fast.len(), n + m - 1);
+
+            for _ in 0..32 {
+                let i = rng.random_range(0..fast.len());
+                let exact: f64 = (i.saturating_sub(m - 1)..=i.min(n - 1))
+                    .map(|j| a[j] * b[i - j])
+                    .sum();
+                assert!((fast[i] - exact).abs() <= FFT_TOLERANCE, "index {}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn sum_distribution_above_fft_threshold() {
+        // The sum of independent `Bin(n_i, p)` is `Bin(sum n_i, p)`
+        let sizes = [2 * FFT_THRESHOLD as u64, 300, 500, FFT_THRESHOLD as u64];
+        let dist: Vec<WeightedDistribution> = sizes
+            .iter()
+            .map(|n| WeightedDistribution::binomial(*n, 0.3).unwrap())
+            .collect();
+
+        let sum = WeightedDistribution::sum_distribution(&dist);
+        let expected = WeightedDistribution::binomial(sizes.iter().sum(), 0.3).unwrap();
+
+        assert!(sum.has_integer_support());
+        let deviation = max_deviation(&sum.get_probs(), &expected.get_probs());
+        assert!(deviation <= FFT_TOLERANCE, "{}", deviation);
+    }
+
+    #[test]
+    fn max_distribution_of_wide_networks_does_not_underflow() {
+        // `P[max <= v] = F(v)^n` is far below `f64::MIN_POSITIVE` for every factor in linear space
+        let n = 10_000;
+        let edge = WeightedDistribution::new(&[1.0 - 2e-4, 1e-4, 1e-4]);
+        let max = WeightedDistribution::max_distribution(&vec![edge.clone(); n]);
+
+        let cdf = |v: f64| (n as f64 * edge.cdf(v).ln()).exp();
+        let expected = [cdf(0.0), cdf(1.0) - cdf(0.0), 1.0 - cdf(1.0)];
+        assert!(max_deviation(&max.get_probs(), &expected) <= 1e-12);
+        assert!(max.mass_error() <= 1e-12);
+
+        // Heterogeneous edges: the result must still be a proper distribution
+        let rng = &mut Pcg64::seed_from_u64(5);
+        let edges: Vec<WeightedDistribution> = (0..n)
+            .map(|_| WeightedDistribution::new(&random_probs(rng, 8)))
+            .collect();
+        let max = WeightedDistribution::max_distribution(&edges);
+        assert!(max.get_probs().iter().all(|p| p.is_finite() && *p >= 0.0));
+        assert!(max.mass_error() <= 1e-12);
+        assert!(is_close(max.value(max.size() - 1), 7.0));
+    }
+
+    #[test]
+    fn derived_distributions_have_no_negative_weights() {
+        // Consecutive CDF values that only differ far below the rounding error of their logarithms
+        let mut weights = vec![1.0; 64];
+        for (i, w) in weights.iter_mut().enumerate().skip(1) {
+            *w = 1e-17 * i as f64;
+        }
+        let edges = vec![WeightedDistribution::new(&weights); 1000];
+
+        // The top-t sums enumerate partial sums and are only reduced over few edges
+        for (reduction, count) in [
+            (Reduction::MAX, 1000),
+            (Reduction::MIN, 1000),
+            (Reduction::SUM, 1000),
+            (Reduction::ORDER(2), 1000),
+            (Reduction::TOP(2), 10),
+        ] {
+            let dist = reduction.distribution(&edges[..count]);
+            assert!(
+                dist.get_probs().iter().all(|p| p.is_finite() && *p >= 0.0),
+                "{:?}",
+                reduction
+            );
+            assert!(dist.mass_error() <= 1e-9, "{:?}", reduction);
+        }
+    }
+
+    #[test]
+    fn rebin_respects_bin_count_and_error_bound() {
+        let lattice = |values: Vec<f64>| {
+            let rng = &mut Pcg64::seed_from_u64(12);
+            let weights = random_probs(rng, values.len());
+            WeightedDistribution::with_support(&values, &weights)
+        };
+        let mixed_sign = lattice((-10..=10).map(f64::from).collect());
+        let offset = lattice((100..=110).map(f64::from).collect());
+        let irregular = lattice(vec![-3.5, -0.25, 0.0, 1.0, 7.5, 8.0, 40.0]);
+
+        for dist in [&mixed_sign, &offset, &irregular] {
+            for bins in 1..=12 {
+                for binning in [Binning::UNIFORM, Binning::QUANTILE] {
+                    let (coarse, error) = dist.rebin(bins, &binning);
+                    assert!(coarse.size() <= bins, "{:?} with {} bins", binning, bins);
+                    assert!(
+                        (dist.expected_value() - coarse.expected_value()).abs() <= error + 1e-12,
+                        "{:?} with {} bins",
+                        binning,
+                        bins
+                    );
+                }
+            }
+        }
+
+        // Equally spaced supports keep exactly `bins` values instead of collapsing
+        for dist in [&mixed_sign, &offset] {
+            for bins in 2..=dist.size() {
+                assert_eq!(dist.rebin(bins, &Binning::UNIFORM).0.size(), bins);
+            }
+        }
+    }
+
+    /// Small independent distributions with integer, real and negative supports
+    fn small_distributions() -> Vec<WeightedDistribution> {
+        vec![
+            WeightedDistribution::new(&[0.2, 0.5, 0.3]),
+            WeightedDistribution::with_support(&[-1.5, 0.0, 2.0], &[0.1, 0.6, 0.3]),
+            WeightedDistribution::with_support(&[0.5, 1.0, 2.0, 4.0], &[0.4, 0.1, 0.3, 0.2]),
+            WeightedDistribution::new(&[0.0, 0.7, 0.1, 0.2]),
+        ]
+    }
+
+    /// Exact distribution of `reduction` by enumerating all joint outcomes as sorted `(value, probability)` pairs
+    fn brute_force(dist: &[WeightedDistribution], reduction: &Reduction) -> Vec<(f64, f64)> {
+        let mut atoms = Vec::new();
+        for outcome in dist.iter().map(|d| 0..d.size()).multi_cartesian_product() {
+            let values = outcome.iter().zip(dist).map(|(i, d)| d.value(*i)).collect();
+            let prob = outcome
+                .iter()
+                .zip(dist)
+                .map(|(i, d)| d.prob_equal(*i))
+                .product();
+            atoms.push((reduction.reduce(values), prob));
+        }
+        merge_atoms(atoms)
+    }
+
+    /// Asserts that the pmf of `dist` equals the `(value, probability)` pairs up to `1e-12`
+    fn assert_pmf(dist: &WeightedDistribution, atoms: &[(f64, f64)], name: &str) {
+        let mut expected = atoms.iter().copied().filter(|(_, p)| *p > 0.0).peekable();
+        for (v, p) in dist.get_values().into_iter().zip(dist.get_probs()) {
+            match expected.peek() {
+                Some((w, q)) if is_close(v, *w) => {
+                    assert!(
+                        (p - q).abs() <= 1e-12,
+                        "{}: P[{}] = {} != {}",
+                        name,
+                        v,
+                        p,
+                        q
+                    );
+                    expected.next();
+                }
+                _ => assert!(p <= 1e-12, "{}: unexpected value {}", name, v),
+            }
+        }
+        assert!(expected.next().is_none(), "{}: missing values", name);
+    }
+
+    #[test]
+    fn reductions_match_brute_force() {
+        let all = small_distributions();
+        for count in 1..=all.len() {
+            let dist = &all[..count];
+            let mut reductions = vec![Reduction::MAX, Reduction::SUM, Reduction::MIN];
+            for j in 1..=count {
+                reductions.push(Reduction::ORDER(j));
+                reductions.push(Reduction::TOP(j));
+            }
+            // Less than `j` edges are 0
+            reductions.push(Reduction::ORDER(count + 1));
+
+            for reduction in reductions {
+                assert_pmf(
+                    &reduction.distribution(dist),
+                    &brute_force(dist, &reduction),
+                    &format!("{:?} of {}", reduction, count),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mixture_matches_brute_force() {
+        let dist = small_distributions();
+        let weights = [1.0, 3.0, 0.0, 4.0];
+        let mixture = WeightedDistribution::mixture_distribution(&dist, &weights).unwrap();
+
+        let atoms: Vec<(f64, f64)> = dist
+            .iter()
+            .zip(weights)
+            .flat_map(|(d, w)| (0..d.size()).map(move |i| (d.value(i), w / 8.0 * d.prob_equal(i))))
+            .collect();
+        assert_pmf(&mixture, &merge_atoms(atoms), "mixture");
+
+        assert!(WeightedDistribution::mixture_distribution(&dist, &[1.0]).is_err());
+        assert!(WeightedDistribution::mixture_distribution(&dist, &[0.0; 4]).is_err());
+    }
+
+    #[test]
+    fn gaussian_posterior_of_two_point_prior() {
+        // Prior P[X = 0] = 0.3, P[X = 2] = 0.7 and sigma = 1: P[X = 2 | y] = 1 / (1 + 3/7 * exp(2 - 2y))
+        let prior = WeightedDistribution::with_support(&[0.0, 2.0], &[0.3, 0.7]);
+        for y in [-50.0, -1.0, 0.0, 0.5, 1.0, 1.7, 3.0, 50.0] {
+            let posterior = prior.gaussian_posterior(y, 1.0);
+            let p2 = 1.0 / (1.0 + 3.0 / 7.0 * (2.0 - 2.0 * y).exp());
+            assert!(
+                (posterior.expected_value() - 2.0 * p2).abs() < 1e-12,
+                "y = {}",
+                y
+            );
+            assert!(is_close(posterior.get_probs().iter().sum::<f64>(), 1.0));
+        }
+
+        // A large sigma keeps the prior, a small sigma concentrates on the closest value
+        let posterior = prior.gaussian_posterior(0.4, 1e6);
+        assert!((posterior.expected_value() - prior.expected_value()).abs() < 1e-9);
+        let posterior = prior.gaussian_posterior(1.2, 1e-3);
+        assert!(is_close(posterior.expected_value(), 2.0));
+    }
+
+    const ALL_FAMILIES: [DistributionFamily; 8] = [
+        DistributionFamily::UNIFORM,
+        DistributionFamily::POISSON,
+        DistributionFamily::BINOMIAL,
+        DistributionFamily::BETABINOMIAL,
+        DistributionFamily::GEOMETRIC,
+        DistributionFamily::NEGBINOMIAL,
+        DistributionFamily::BERNOULLI,
+        DistributionFamily::EMPIRICAL,
+    ];
+
+    #[test]
+    fn invalid_distributions_are_rejected() {
+        use DistributionError::*;
+        let with_support = WeightedDistribution::try_with_support;
+
+        assert_eq!(WeightedDistribution::try_new(&[]).unwrap_err(), Empty);
+        assert_eq!(with_support(&[], &[]).unwrap_err(), Empty);
+        assert_eq!(WeightedDistribution::empirical(&[]).unwrap_err(), Empty);
+
+        assert_eq!(
+            with_support(&[0.0, 1.0], &[1.0]).unwrap_err(),
+            LengthMismatch {
+                values: 2,
+                weights: 1
+            }
+        );
+
+        assert_eq!(
+            WeightedDistribution::try_new(&[0.5, -0.5, 1.0]).unwrap_err(),
+            InvalidWeight {
+                index: 1,
+                weight: -0.5
+            }
+        );
+        for weight in [f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                WeightedDistribution::try_new(&[1.0, 1.0, weight]),
+                Err(InvalidWeight { index: 2, .. })
+            ));
+        }
+        // Rounding errors below 0 are clamped
+        assert!(WeightedDistribution::try_new(&[1.0, -1e-12]).is_ok());
+
+        assert_eq!(
+            WeightedDistribution::try_new(&[0.0, 0.0]).unwrap_err(),
+            ZeroTotalWeight
+        );
+
+        for value in [f64::NAN, f64::NEG_INFINITY] {
+            assert!(matches!(
+                with_support(&[0.0, value], &[1.0, 1.0]),
+                Err(InvalidValue { index: 1, .. })
+            ));
+        }
+
+        assert_eq!(
+            with_support(&[0.0, 2.0, 1.0], &[1.0; 3]).unwrap_err(),
+            UnsortedSupport { index: 2 }
+        );
+        assert_eq!(
+            with_support(&[0.0, 1.0, 1.0], &[1.0; 3]).unwrap_err(),
+            UnsortedSupport { index: 2 }
+        );
+
+        let parameter = |result: Result<WeightedDistribution, DistributionError>| match result {
+            Err(InvalidParameter { name, .. }) => name,
+            _ => panic!("Expected an invalid parameter"),
+        };
+        assert_eq!(parameter(WeightedDistribution::binomial(3, 1.5)), "p");
+        assert_eq!(
+            parameter(WeightedDistribution::beta_binomial(3, 0.0, 1.0)),
+            "alpha"
+        );
+        assert_eq!(
+            parameter(WeightedDistribution::beta_binomial(3, 1.0, f64::INFINITY)),
+            "beta"
+        );
+        assert_eq!(parameter(WeightedDistribution::poisson(-1.0, 5)), "lambda");
+        assert_eq!(parameter(WeightedDistribution::geometric(0.0, 5)), "p");
+        assert_eq!(
+            parameter(WeightedDistribution::negative_binomial(0.0, 0.5, 5)),
+            "r"
+        );
+        assert_eq!(
+            parameter(WeightedDistribution::bernoulli_scaled(2.0, 1.0)),
+            "p"
+        );
+        assert_eq!(
+            parameter(WeightedDistribution::bernoulli_scaled(0.5, f64::NAN)),
+            "value"
+        );
+
+        // Random distributions surface errors instead of panicking
+        let rng = &mut Pcg64::seed_from_u64(10);
+        for family in &ALL_FAMILIES {
+            assert_eq!(
+                create_random_distribution(rng, family, 0).unwrap_err(),
+                Empty
+            );
+            for vs in 0..5 {
+                assert!(BipartiteRegulatorProbing::create_random(rng, 3, 2, vs, family).is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn moments_quantiles_and_entropy() {
+        let close = |a: f64, b: f64| assert!((a - b).abs() < 1e-12, "{} != {}", a, b);
+        let close_some = |a: Option<f64>, b: Option<f64>| match (a, b) {
+            (Some(a), Some(b)) => close(a, b),
+            _ => assert_eq!(a, b),
+        };
+
+        // Uniform over {0, 1, 2, 3}
+        let uniform = WeightedDistribution::new(&[1.0; 4]);
+        close(uniform.expected_value(), 1.5);
+        close(uniform.variance(), 1.25);
+        close(uniform.std_dev(), 1.25f64.sqrt());
+        close(uniform.entropy(), 4f64.ln());
+        for (q, x) in [
+            (0.0, 0.0),
+            (0.25, 0.0),
+            (0.26, 1.0),
+            (0.5, 1.0),
+            (0.75, 2.0),
+            (0.9, 3.0),
+            (1.0, 3.0),
+        ] {
+            assert_eq!(uniform.quantile(q), x, "q = {}", q);
+        }
+        for (t, e) in [
+            (-10.0, Some(1.5)),
+            (1.0, Some(2.0)),
+            (1.5, Some(2.5)),
+            (3.0, Some(3.0)),
+            (3.5, None),
+            (100.0, None),
+        ] {
+            close_some(uniform.conditional_expectation(t), e);
+        }
+
+        // Bernoulli with P[X = 1] = 0.3
+        let bernoulli = WeightedDistribution::new(&[0.7, 0.3]);
+        close(bernoulli.expected_value(), 0.3);
+        close(bernoulli.variance(), 0.21);
+        close(
+            bernoulli.entropy(),
+            -(0.3f64 * 0.3f64.ln() + 0.7 * 0.7f64.ln()),
+        );
+        assert_eq!(bernoulli.quantile(0.0), 0.0);
+        assert_eq!(bernoulli.quantile(0.7), 0.0);
+        assert_eq!(bernoulli.quantile(0.7 + 1e-9), 1.0);
+        assert_eq!(bernoulli.quantile(1.0), 1.0);
+        close_some(bernoulli.conditional_expectation(0.5), Some(1.0));
+        assert_eq!(bernoulli.conditional_expectation(1.5), None);
+
+        // Real support with a zero weight, which adds nothing to the entropy
+        let real =
+            WeightedDistribution::with_support(&[-1.0, 0.5, 2.0, 4.0], &[0.2, 0.3, 0.5, 0.0]);
+        close(real.expected_value(), 0.95);
+        close(real.variance(), 0.2 + 0.3 * 0.25 + 0.5 * 4.0 - 0.95 * 0.95);
+        close(
+            real.entropy(),
+            -(0.2f64 * 0.2f64.ln() + 0.3 * 0.3f64.ln() + 0.5 * 0.5f64.ln()),
+        );
+        assert_eq!(real.quantile(0.2), -1.0);
+        assert_eq!(real.quantile(0.5), 0.5);
+        assert_eq!(real.quantile(1.0), 2.0);
+        close(
+            real.conditional_expectation(0.0).unwrap(),
+            (0.15 + 1.0) / 0.8,
+        );
+        assert_eq!(real.conditional_expectation(2.5), None);
+
+        // Point masses have no variance and no entropy
+        for point in [
+            WeightedDistribution::new(&[1.0]),
+            WeightedDistribution::with_support(&[7.5], &[1.0]),
+        ] {
+            assert_eq!(point.variance(), 0.0);
+            assert_eq!(point.std_dev(), 0.0);
+            assert_eq!(point.entropy(), 0.0);
+            assert_eq!(point.quantile(0.0), point.quantile(1.0));
+        }
+    }
+
+    #[test]
+    fn families_match_their_means() {
+        let check =
+            |dist: Result<WeightedDistribution, DistributionError>, mean: f64, name: &str| {
+                let dist = dist.unwrap();
+                assert!(
+                    is_close(dist.get_probs().iter().sum::<f64>(), 1.0),
+                    "{}",
+                    name
+                );
+                assert!(
+                    (dist.expected_value() - mean).abs() < 1e-9,
+                    "{}: {} != {}",
+                    name,
+                    dist.expected_value(),
+                    mean
+                );
+            };
+
+        check(WeightedDistribution::binomial(10, 0.3), 3.0, "Bin(10, 0.3)");
+        check(
+            WeightedDistribution::beta_binomial(12, 2.0, 6.0),
+            3.0,
+            "BetaBin(12, 2, 6)",
+        );
+        check(
+            WeightedDistribution::beta_binomial(1, 0.5, 0.5),
+            0.5,
+            "BetaBin(1, 0.5, 0.5)",
+        );
+        check(WeightedDistribution::poisson(2.5, 100), 2.5, "Poi(2.5)");
+        check(
+            WeightedDistribution::geometric(0.3, 200),
+            0.7 / 0.3,
+            "Geo(0.3)",
+        );
+        check(
+            WeightedDistribution::negative_binomial(3.0, 0.4, 300),
+            4.5,
+            "NB(3, 0.4)",
+        );
+        check(
+            WeightedDistribution::negative_binomial(2.0, 1.0, 5),
+            0.0,
+            "NB(2, 1)",
+        );
+        check(
+            WeightedDistribution::bernoulli_scaled(0.25, 8.0),
+            2.0,
+            "8 * Ber(0.25)",
+        );
+        check(
+            WeightedDistribution::bernoulli_scaled(0.25, -8.0),
+            -2.0,
+            "-8 * Ber(0.25)",
+        );
+        check(
+            WeightedDistribution::bernoulli_scaled(0.25, 0.0),
+            0.0,
+            "0 * Ber(0.25)",
+        );
+        check(
+            WeightedDistribution::empirical(&[3.0, 1.0, 3.0, -2.5, 3.0]),
+            1.5,
+            "Empirical",
+        );
+
+        // Truncation moves the tail mass onto the largest value and lowers the mean
+        for (dist, mean) in [
+            (WeightedDistribution::poisson(2.5, 4), 2.5),
+            (WeightedDistribution::geometric(0.3, 5), 0.7 / 0.3),
+            (WeightedDistribution::negative_binomial(3.0, 0.4, 6), 4.5),
+        ] {
+            let dist = dist.unwrap();
+            assert!(is_close(dist.get_probs().iter().sum::<f64>(), 1.0));
+            assert!(dist.expected_value() < mean);
+        }
+
+        let empirical = WeightedDistribution::empirical(&[3.0, 1.0, 3.0, -2.5, 3.0]).unwrap();
+        assert_eq!(empirical.get_values(), vec![-2.5, 1.0, 3.0]);
+        assert!(is_close(empirical.cdf(1.0), 0.4));
+
+        // Random distributions of every family are normalized
+        let rng = &mut Pcg64::seed_from_u64(8);
+        for family in &ALL_FAMILIES {
+            for vs in 1..8 {
+                let dist = create_random_distribution(rng, family, vs).unwrap();
+                assert!(dist.size() <= vs, "{:?}", family);
+                assert!(is_close(dist.get_probs().iter().sum::<f64>(), 1.0));
+            }
+        }
+    }
+
+    #[test]
+    fn order_needs_enough_values() {
+        assert!(matches!(
+            Reduction::order(0),
+            Err(DistributionError::InvalidParameter { name: "j", .. })
+        ));
+        assert_eq!(Reduction::order(2), Ok(Reduction::ORDER(2)));
+
+        let order = Reduction::order(3).unwrap();
+        assert_eq!(order.reduce(vec![4.0, 1.0, 7.0, 2.0]), 2.0);
+        assert_eq!(order.reduce(vec![4.0, 7.0]), 0.0);
+        assert_eq!(order.reduce(Vec::new()), 0.0);
+
+        let dist = vec![WeightedDistribution::with_support(&[1.0, 5.0], &[1.0, 1.0]); 2];
+        let zero = order.distribution(&dist);
+        assert_eq!(zero.get_values(), vec![0.0]);
+        assert_eq!(zero.get_probs(), vec![1.0]);
+        assert_eq!(order.distribution(&[]).get_values(), vec![0.0]);
+    }
 }
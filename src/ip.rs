@@ -1,10 +1,25 @@
 use highs::{HighsModelStatus, RowProblem};
 use itertools::Itertools;
 
-pub fn solve_cov_instance(na: usize, nb: usize, l: usize, weights: &[Vec<usize>]) -> usize {
+use crate::model::GroupConstraint;
+
+/// Value of the LP-relaxation of weighted Max-Coverage `max sum_b w_b * max_{a in S} weights[a][b]` with `|S| <= l`
+/// and at most `capacities[g]` regulators of every group `g` of the optional constraint.
+/// `weights[a][i]` is the weight of edge `(a, positions[a][i])`, absent edges have weight 0 and no variable.
+pub fn solve_cov_instance(
+    na: usize,
+    nb: usize,
+    l: usize,
+    positions: &[Vec<usize>],
+    weights: &[Vec<f64>],
+    position_weights: &[f64],
+    constraint: Option<&GroupConstraint>,
+) -> f64 {
     debug_assert_eq!(weights.len(), na);
+    debug_assert_eq!(positions.len(), na);
+    debug_assert_eq!(position_weights.len(), nb);
     for a in 0..na {
-        debug_assert_eq!(weights[a].len(), nb);
+        debug_assert_eq!(weights[a].len(), positions[a].len());
     }
 
     let mut model = RowProblem::default();
@@ -15,27 +30,36 @@ pub fn solve_cov_instance(na: usize, nb: usize, l: usize, weights: &[Vec<usize>]
         .map(|_| model.add_column(0.0, 0.0..=1.0))
         .collect_vec();
 
-    // Variable for each edge
-    let edges = (0..na)
-        .map(|a| {
-            (0..nb)
-                .map(|b| model.add_column(weights[a][b] as f64, 0.0..=1.0))
-                .collect_vec()
-        })
-        .collect_vec();
-
-    for b in 0..nb {
-        // Each Gen/Position can be covered at most once
-        model.add_row(0.0..=1.0, (0..na).map(|a| (edges[a][b], 1.0)));
+    // Variable for each edge and the edge variables of every Position
+    let mut position_edges = vec![Vec::new(); nb];
+    for a in 0..na {
+        for (&b, &w) in positions[a].iter().zip(&weights[a]) {
+            let edge = model.add_column(position_weights[b] * w, 0.0..=1.0);
+            position_edges[b].push(edge);
 
-        for a in 0..na {
-            // edges[a][b] <= regs[a]
-            model.add_row(..=0.0, [(edges[a][b], 1.0), (regs[a], -1.0)]);
+            // edge <= regs[a]
+            model.add_row(..=0.0, [(edge, 1.0), (regs[a], -1.0)]);
         }
     }
 
+    for edges in position_edges {
+        // Each Gen/Position can be covered at most once
+        model.add_row(0.0..=1.0, edges.into_iter().map(|edge| (edge, 1.0)));
+    }
+
     model.add_row(0.0..=(l as f64), (0..na).map(|a| (regs[a], 1.0)));
 
+    if let Some(constraint) = constraint {
+        for (g, capacity) in constraint.capacities.iter().enumerate() {
+            model.add_row(
+                0.0..=(*capacity as f64),
+                (0..na)
+                    .filter(|a| constraint.groups[*a] == Some(g))
+                    .map(|a| (regs[a], 1.0)),
+            );
+        }
+    }
+
     let solved = model.optimise(highs::Sense::Maximise).solve();
 
     assert_eq!(solved.status(), HighsModelStatus::Optimal);
@@ -46,21 +70,22 @@ pub fn solve_cov_instance(na: usize, nb: usize, l: usize, weights: &[Vec<usize>]
 
     //let mut sol_regs = Vec::with_capacity(l);
     let mut obj_val = 0.0;
+    let mut idx = na;
     for a in 0..na {
         // Values should be integral, but sometimes the value is not 1.0 due to floating point approx
         //if sol_vars[a] > 0.5 {
         //    sol_regs.push(a);
         //}
 
-        for b in 0..nb {
-            let idx = na + nb * a + b;
-            obj_val += (weights[a][b] as f64) * sol_vars[idx];
+        for (&b, &w) in positions[a].iter().zip(&weights[a]) {
+            obj_val += position_weights[b] * w * sol_vars[idx];
+            idx += 1;
         }
     }
 
     //let real_obj = (0..nb).map(|b| sol_regs.iter().map(|&a| weights[a][b]).max().unwrap()).sum();
-    // assert_eq!(real_obj, obj_val as usize);
+    // assert!(is_close(real_obj, obj_val));
     //(real_obj, sol_regs)
 
-    obj_val.floor() as usize
+    obj_val
 }
@@ -3,10 +3,16 @@
 
 use std::str::FromStr;
 
+use rand_pcg::Pcg64;
+
+use crate::distributions::Reduction;
+
 pub mod algorithms;
 pub mod distributions;
 pub mod ip;
 pub mod model;
+pub mod policies;
+pub mod storage;
 
 /// Possible GoalFunctions
 #[derive(Debug, Clone, PartialEq)]
@@ -20,7 +26,7 @@ pub enum GoalFunction {
 }
 
 /// Possible Algorithms
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Algorithm {
     /// Adaptive-Myopic-Policy
     AMP,
@@ -28,10 +34,99 @@ pub enum Algorithm {
     NAMP,
     /// Optimal-Offline-Algorithm
     OPT,
+    /// Non-Adaptive-Myopic-Policy computed for `l = k` (for Coverage only)
+    EXT,
+    /// Adaptive-Myopic-Policy probing in batches over a given number of rounds
+    BAMP,
     /// All Algorithms above
     ALL,
 }
 
+impl Algorithm {
+    /// All single Algorithms (i.e. all but `ALL`) in the order in which they are run
+    pub const ALGORITHMS: [Algorithm; 5] = [
+        Algorithm::OPT,
+        Algorithm::AMP,
+        Algorithm::NAMP,
+        Algorithm::EXT,
+        Algorithm::BAMP,
+    ];
+}
+
+/// Possible Families of Edge-Distributions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DistributionFamily {
+    /// Random weights on every value of the support
+    UNIFORM,
+    /// Truncated Poisson-Distribution
+    POISSON,
+    /// Binomial-Distribution
+    BINOMIAL,
+    /// Beta-Binomial-Distribution
+    BETABINOMIAL,
+    /// Truncated Geometric-Distribution
+    GEOMETRIC,
+    /// Truncated Negative-Binomial-Distribution
+    NEGBINOMIAL,
+    /// Bernoulli-Distribution scaled to the largest value of the support
+    BERNOULLI,
+    /// Empirical Distribution of samples
+    EMPIRICAL,
+}
+
+/// Possible Binnings of a support onto fewer values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binning {
+    /// Bins of equal width on a grid shared by all distributions
+    UNIFORM,
+    /// Bins of (about) equal probability
+    QUANTILE,
+}
+
+/// Possible Formats of saved BPR-models
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageFormat {
+    /// Human-readable JSON
+    JSON,
+    /// Compact little-endian binary format
+    BINARY,
+}
+
+/// Possible Rules to merge several Regulators (e.g. motif variants of a TF) into one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeRule {
+    /// Largest edge of all variants
+    MAX,
+    /// Sum of the edges of all variants
+    SUM,
+    /// Smallest edge of all variants
+    MIN,
+}
+
+impl MergeRule {
+    /// Reduction applied to the edges of the merged Regulators
+    pub fn reduction(&self) -> Reduction {
+        match self {
+            MergeRule::MAX => Reduction::MAX,
+            MergeRule::SUM => Reduction::SUM,
+            MergeRule::MIN => Reduction::MIN,
+        }
+    }
+}
+
+/// Number of Regulators given absolutely or as a fraction of all Regulators
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Count {
+    /// Exact number
+    ABSOLUTE(usize),
+    /// Fraction in `[0, 1]` of all Regulators (rounded down)
+    FRACTION(f64),
+}
+
+/// Pair of the number of probed (k) and selected (l) Regulators
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KLPair(pub Count, pub Count);
+
 /// Allow parsing GoalFunction from String
 impl FromStr for GoalFunction {
     type Err = &'static str;
@@ -54,28 +149,116 @@ impl FromStr for Algorithm {
             "ALL" => Ok(Algorithm::ALL),
             "OPT" => Ok(Algorithm::OPT),
             "NAMP" => Ok(Algorithm::NAMP),
+            "EXT" => Ok(Algorithm::EXT),
+            "BAMP" => Ok(Algorithm::BAMP),
             _ => Err("Could not parse Algorithm!"),
         }
     }
 }
 
+/// Allow parsing DistributionFamily from String
+impl FromStr for DistributionFamily {
+    type Err = &'static str;
+    fn from_str(family: &str) -> Result<Self, Self::Err> {
+        match family {
+            "UNIFORM" => Ok(DistributionFamily::UNIFORM),
+            "POISSON" => Ok(DistributionFamily::POISSON),
+            "BINOMIAL" => Ok(DistributionFamily::BINOMIAL),
+            "BETABINOMIAL" => Ok(DistributionFamily::BETABINOMIAL),
+            "GEOMETRIC" => Ok(DistributionFamily::GEOMETRIC),
+            "NEGBINOMIAL" => Ok(DistributionFamily::NEGBINOMIAL),
+            "BERNOULLI" => Ok(DistributionFamily::BERNOULLI),
+            "EMPIRICAL" => Ok(DistributionFamily::EMPIRICAL),
+            _ => Err("Could not parse DistributionFamily!"),
+        }
+    }
+}
+
+/// Allow parsing Binning from String
+impl FromStr for Binning {
+    type Err = &'static str;
+    fn from_str(binning: &str) -> Result<Self, Self::Err> {
+        match binning {
+            "UNIFORM" => Ok(Binning::UNIFORM),
+            "QUANTILE" => Ok(Binning::QUANTILE),
+            _ => Err("Could not parse Binning!"),
+        }
+    }
+}
+
+/// Allow parsing StorageFormat from String
+impl FromStr for StorageFormat {
+    type Err = &'static str;
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "JSON" => Ok(StorageFormat::JSON),
+            "BINARY" => Ok(StorageFormat::BINARY),
+            _ => Err("Could not parse StorageFormat!"),
+        }
+    }
+}
+
+/// Allow parsing MergeRule from String
+impl FromStr for MergeRule {
+    type Err = &'static str;
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        match rule {
+            "MAX" => Ok(MergeRule::MAX),
+            "SUM" => Ok(MergeRule::SUM),
+            "MIN" => Ok(MergeRule::MIN),
+            _ => Err("Could not parse MergeRule!"),
+        }
+    }
+}
+
+/// Allow parsing Count from String: integers are absolute, decimals are fractions
+impl FromStr for Count {
+    type Err = &'static str;
+    fn from_str(count: &str) -> Result<Self, Self::Err> {
+        if !count.contains('.') {
+            return count
+                .parse()
+                .map(Count::ABSOLUTE)
+                .map_err(|_| "Could not parse Count!");
+        }
+
+        match count.parse::<f64>() {
+            Ok(f) if (0.0..=1.0).contains(&f) => Ok(Count::FRACTION(f)),
+            _ => Err("Could not parse Count (fractions must be in [0, 1])!"),
+        }
+    }
+}
+
+impl Count {
+    /// Number of Regulators out of `n`
+    pub fn resolve(&self, n: usize) -> usize {
+        match *self {
+            Count::ABSOLUTE(c) => c,
+            Count::FRACTION(f) => (f * n as f64).floor() as usize,
+        }
+    }
+}
+
+/// Allow parsing KLPair from String of the form `k:l`
+impl FromStr for KLPair {
+    type Err = &'static str;
+    fn from_str(pair: &str) -> Result<Self, Self::Err> {
+        match pair.split_once(':') {
+            Some((k, l)) => Ok(KLPair(k.parse()?, l.parse()?)),
+            None => Err("Could not parse KLPair (expected k:l)!"),
+        }
+    }
+}
+
 /// Are two f64 close enough to be considered the same
 pub fn is_close(a: f64, b: f64) -> bool {
     (b - a).abs() < 1e-09
 }
 
-pub fn compute_opt_l_values(n: usize) -> [usize; 8] {
-    let n16 = n / 16;
-    [
-        n16,
-        n16 * 2,
-        n16 * 3,
-        n16 * 4,
-        n16 * 6,
-        n16 * 8,
-        n16 * 9,
-        n16 * 12,
-    ]
+/// Independent random stream for one iteration of an experiment.
+/// Only depends on `seed` and `iteration`, so results are reproducible regardless of thread scheduling.
+pub fn iteration_rng(seed: u64, iteration: usize) -> Pcg64 {
+    Pcg64::new(seed as u128, iteration as u128)
 }
 
 pub fn compute_k_l_pairs(n: usize) -> [(usize, usize); 12] {
@@ -96,3 +279,160 @@ pub fn compute_k_l_pairs(n: usize) -> [(usize, usize); 12] {
         (n4 * 3, n4 * 3),
     ]
 }
+
+/// Build the (k, l) pairs of an experiment on `n` Regulators from explicit `pairs` and all combinations
+/// of `k_values` and `l_values` with `l <= k`. Without any of them, `compute_k_l_pairs` is used and
+/// `full` yields all pairs `1 <= l <= k <= n` and cannot be combined with them. Fails if a given pair violates
+/// `1 <= l <= k <= n` or if the grid is empty.
+pub fn compute_k_l_grid(
+    n: usize,
+    pairs: &[KLPair],
+    k_values: &[Count],
+    l_values: &[Count],
+    full: bool,
+) -> Result<Vec<(usize, usize)>, String> {
+    if k_values.is_empty() != l_values.is_empty() {
+        return Err("Values of k and l must be given together!".to_string());
+    }
+    if full && !(pairs.is_empty() && k_values.is_empty()) {
+        return Err(
+            "The full grid cannot be combined with pairs or values of k and l!".to_string(),
+        );
+    }
+
+    let mut grid: Vec<(usize, usize)> = if full {
+        (1..=n).flat_map(|k| (1..=k).map(move |l| (k, l))).collect()
+    } else if pairs.is_empty() && k_values.is_empty() {
+        // Drop the degenerate pairs of the default grid for `n < 16`
+        compute_k_l_pairs(n)
+            .iter()
+            .copied()
+            .filter(|(_, l)| *l > 0)
+            .collect()
+    } else {
+        pairs
+            .iter()
+            .map(|KLPair(k, l)| (k.resolve(n), l.resolve(n)))
+            .chain(k_values.iter().flat_map(|k| {
+                let k = k.resolve(n);
+                l_values
+                    .iter()
+                    .map(move |l| (k, l.resolve(n)))
+                    .filter(|(k, l)| l <= k)
+            }))
+            .collect()
+    };
+
+    if let Some((k, l)) = grid.iter().find(|(k, l)| *l == 0 || l > k || *k > n) {
+        return Err(format!(
+            "Invalid pair k = {}, l = {}: 1 <= l <= k <= {} is required!",
+            k, l, n
+        ));
+    }
+
+    if grid.is_empty() {
+        return Err(format!("No pairs (k, l) with 1 <= l <= k <= {}!", n));
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    grid.retain(|pair| seen.insert(*pair));
+
+    Ok(grid)
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use rayon::prelude::*;
+
+    use super::*;
+    use crate::model::BipartiteRegulatorProbing;
+
+    fn grid(
+        n: usize,
+        pairs: &str,
+        k: &str,
+        l: &str,
+        full: bool,
+    ) -> Result<Vec<(usize, usize)>, String> {
+        let parse = |values: &str| -> Vec<Count> {
+            values
+                .split(',')
+                .filter(|v| !v.is_empty())
+                .map(|v| v.parse().unwrap())
+                .collect()
+        };
+        let pairs: Vec<KLPair> = pairs
+            .split(',')
+            .filter(|p| !p.is_empty())
+            .map(|p| p.parse().unwrap())
+            .collect();
+        compute_k_l_grid(n, &pairs, &parse(k), &parse(l), full)
+    }
+
+    #[test]
+    fn k_l_grid() {
+        assert_eq!(grid(16, "", "", "", false).unwrap(), compute_k_l_pairs(16));
+        assert_eq!(
+            grid(3, "", "", "", true).unwrap(),
+            vec![(1, 1), (2, 1), (2, 2), (3, 1), (3, 2), (3, 3)]
+        );
+        assert_eq!(
+            grid(8, "4:2,0.5:0.25", "2,4", "1,3", false).unwrap(),
+            vec![(4, 2), (2, 1), (4, 1), (4, 3)]
+        );
+
+        // k > n, l > k and l = 0 are rejected
+        assert!(grid(8, "9:1", "", "", false).is_err());
+        assert!(grid(8, "", "9", "1", false).is_err());
+        assert!(grid(8, "2:3", "", "", false).is_err());
+        assert!(grid(8, "2:0", "", "", false).is_err());
+
+        // Empty grids
+        assert!(grid(8, "", "2", "4", false).is_err());
+        assert!(grid(3, "", "", "", false).is_err());
+
+        // The full grid cannot be combined with other pairs
+        assert!(grid(8, "4:2", "", "", true).is_err());
+        assert!(grid(8, "", "4", "2", true).is_err());
+        assert!(grid(8, "", "4", "", false).is_err());
+    }
+
+    /// Realizations of the first instances of the model of an iteration
+    fn iteration_realizations(seed: u64, iteration: usize) -> Vec<Vec<Vec<f64>>> {
+        let rng = &mut iteration_rng(seed, iteration);
+        let bpr =
+            BipartiteRegulatorProbing::create_random(rng, 6, 5, 4, &DistributionFamily::BINOMIAL)
+                .unwrap();
+        (0..4).rev().map(|j| bpr.sample_realizations(j)).collect()
+    }
+
+    #[test]
+    fn iterations_are_reproducible() {
+        let sequential: Vec<_> = (0..8).map(|i| iteration_realizations(7, i)).collect();
+
+        // Same seed and iteration, same models and instances
+        assert_eq!(sequential[3], iteration_realizations(7, 3));
+        // Different iterations, seeds and instances use different streams
+        assert!(sequential.iter().map(|r| format!("{:?}", r)).all_unique());
+        assert_ne!(sequential[0], iteration_realizations(8, 0));
+        assert!(sequential[0]
+            .iter()
+            .map(|r| format!("{:?}", r))
+            .all_unique());
+
+        // The order in which rayon runs the iterations does not matter
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let parallel: Vec<_> = pool.install(|| {
+            (0..8)
+                .into_par_iter()
+                .rev()
+                .map(|i| iteration_realizations(7, i))
+                .collect()
+        });
+        assert_eq!(parallel.into_iter().rev().collect::<Vec<_>>(), sequential);
+    }
+}
@@ -2,15 +2,19 @@ use std::{
     fs::{File, OpenOptions},
     io::Write,
     path::PathBuf,
-    time::Instant,
 };
 
 use bpr::{
-    compute_k_l_pairs, compute_opt_l_values,
+    compute_k_l_grid, iteration_rng,
     model::{BipartiteRegulatorProbing, ProbeMax},
-    GoalFunction,
+    policies::{Policy, PolicyOptions, ProbingModel},
+    storage::{load_model, save_model},
+    Algorithm, Binning, Count, DistributionFamily, GoalFunction, KLPair, StorageFormat,
 };
 
+use itertools::Itertools;
+use rand::Rng;
+
 use rayon::prelude::*;
 use serde_derive::Serialize;
 use structopt::StructOpt;
@@ -42,12 +46,92 @@ struct Parameters {
     #[structopt(long, default_value = "MAX")]
     goal: GoalFunction,
 
+    /// Algorithms to run: ALL or a comma-separated list such as AMP,EXT
+    #[structopt(long, default_value = "ALL", use_delimiter = true)]
+    algorithm: Vec<Algorithm>,
+
+    /// Numbers of rounds of BAMP, which probes k / rounds Regulators per round (one BAMP-run each)
+    #[structopt(long, use_delimiter = true)]
+    rounds: Vec<usize>,
+
+    /// Do not run OPT (which is run otherwise regardless of --algorithm)
+    #[structopt(long)]
+    not_opt: bool,
+
+    /// (k, l) pairs to run, each value absolute or as a fraction of na, e.g. 8:2,0.5:0.125
+    #[structopt(long, use_delimiter = true)]
+    pairs: Vec<KLPair>,
+
+    /// Values of k (absolute or fractions of na), run with every value of --l that is at most k
+    #[structopt(long = "k", use_delimiter = true)]
+    k_values: Vec<Count>,
+
+    /// Values of l (absolute or fractions of na), run with every value of --k that is at least l
+    #[structopt(long = "l", use_delimiter = true)]
+    l_values: Vec<Count>,
+
+    /// Run all pairs 1 <= l <= k <= na (not combinable with --pairs, --k and --l)
     #[structopt(long)]
-    poisson: bool,
+    full_grid: bool,
+
+    /// Probe within these total probing costs instead of k Regulators (for AMP and NAMP), run with every l of the grid
+    #[structopt(long, use_delimiter = true)]
+    budget: Vec<f64>,
+
+    /// Probe single edges instead of Regulators within these numbers of edges (COV with AMP and NAMP), run with every l of the grid
+    #[structopt(long, use_delimiter = true)]
+    edge_budget: Vec<usize>,
+
+    /// Draw the probing cost of every Regulator uniformly from [1, max-cost] (all costs are 1 otherwise)
+    #[structopt(long)]
+    max_cost: Option<f64>,
+
+    /// Family of the random Edge-Distributions
+    #[structopt(long, default_value = "UNIFORM")]
+    distribution: DistributionFamily,
 
     /// Use the IP-Formulation instead when computing Opt (for Coverage only)
     #[structopt(long)]
     ipopt: bool,
+
+    /// Seed of the experiment (chosen randomly if not given)
+    #[structopt(long)]
+    seed: Option<u64>,
+
+    /// Correlation of the edges of a Regulator in [0, 1] (Gaussian copula with a latent Regulator strength)
+    #[structopt(long, default_value = "0.0")]
+    correlation: f64,
+
+    /// Standard deviation of the Gaussian noise of every probe (overrides the noise of loaded models).
+    /// Policies then act on posterior distributions and selections are scored on the true realizations
+    #[structopt(long)]
+    noise: Option<f64>,
+
+    /// Map every edge onto at most this many support values
+    #[structopt(long)]
+    bins: Option<usize>,
+
+    /// Binning used for --bins: UNIFORM or QUANTILE
+    #[structopt(long, default_value = "UNIFORM")]
+    binning: Binning,
+
+    /// Save the model of every iteration (with the realizations of its instances) into this directory
+    #[structopt(long, parse(from_os_str))]
+    dump: Option<PathBuf>,
+
+    /// Format of saved models: JSON or BINARY
+    #[structopt(long, default_value = "BINARY")]
+    format: StorageFormat,
+
+    /// Run the algorithms on saved models (one iteration per model) instead of random ones
+    #[structopt(long, parse(from_os_str))]
+    load: Vec<PathBuf>,
+
+    #[structopt(skip)]
+    models: Vec<BipartiteRegulatorProbing>,
+
+    #[structopt(skip)]
+    grid: Vec<(usize, usize)>,
 }
 
 #[derive(Serialize)]
@@ -59,18 +143,98 @@ struct Result {
     algo: String,
     k: usize,
     l: usize,
-    val: usize,
+    val: f64,
     ins_id: usize,
     iter_id: usize,
     time: f64,
+    seed: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rounds: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    budget: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spent: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    edge_budget: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    probed_edges: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    noise: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mass_error: Option<f64>,
 }
 
 fn main() -> std::io::Result<()> {
-    let params = Parameters::from_args();
+    let mut params = Parameters::from_args();
+    params.seed.get_or_insert_with(rand::random);
 
     assert!(params.log.is_some(), "Log Path must be given!");
+    assert!(
+        (0.0..=1.0).contains(&params.correlation),
+        "Correlation must be in [0, 1]!"
+    );
+    assert!(
+        params.noise.is_none_or(|s| s.is_finite() && s >= 0.0),
+        "Noise must be non-negative!"
+    );
+    assert!(
+        params.max_cost.is_none_or(|c| c >= 1.0),
+        "Maximum cost must be at least 1!"
+    );
+    assert!(
+        params.budget.iter().all(|b| *b >= 0.0),
+        "Budgets must be non-negative!"
+    );
+    assert!(
+        params.rounds.iter().all(|r| *r > 0),
+        "Numbers of rounds must be positive!"
+    );
+    assert!(
+        params.edge_budget.is_empty() || params.goal == GoalFunction::COV,
+        "Edges can only be probed for COV!"
+    );
+
+    if params.correlation > 0.0 && params.goal != GoalFunction::COV {
+        eprintln!(
+            "Edges are correlated: the NAMP- and AMP-expectations of the boxes are approximations"
+        );
+    }
 
     std::fs::create_dir_all(params.log.as_ref().unwrap())?;
+    if let Some(dump) = &params.dump {
+        std::fs::create_dir_all(dump)?;
+    }
+
+    if !params.load.is_empty() {
+        params.models = params
+            .load
+            .iter()
+            .map(|path| load_model(path))
+            .collect::<std::io::Result<_>>()?;
+
+        let (na, nb) = (params.models[0].get_na(), params.models[0].get_nb());
+        assert!(
+            params
+                .models
+                .iter()
+                .all(|bpr| bpr.get_na() == na && bpr.get_nb() == nb),
+            "All loaded models must have the same number of Regulators and Positions!"
+        );
+
+        params.na = na;
+        params.nb = nb;
+        params.vs = params.models.iter().map(|bpr| bpr.get_vs()).max().unwrap();
+        params.iterations = params.models.len();
+    }
+
+    params.grid = compute_k_l_grid(
+        params.na,
+        &params.pairs,
+        &params.k_values,
+        &params.l_values,
+        params.full_grid,
+    )
+    .map_err(std::io::Error::other)?;
 
     let logfiles: Vec<File> = (0..params.iterations)
         .map(|i| {
@@ -91,274 +255,221 @@ fn main() -> std::io::Result<()> {
         .unwrap_or_else(|e| println!("Failed to set the number of threads used by rayon: {}", e));
 
     match params.goal {
-        GoalFunction::MAX => eval_max(&params, logfiles),
-        GoalFunction::SUM => eval_sum(&params, logfiles),
-        GoalFunction::COV => eval_cov(&params, logfiles),
+        GoalFunction::MAX => eval(&params, logfiles, ProbeMax::from_bpr_max),
+        GoalFunction::SUM => eval(&params, logfiles, ProbeMax::from_bpr_sum),
+        GoalFunction::COV => eval(&params, logfiles, |bpr| bpr),
     };
 
     Ok(())
 }
 
-fn eval_max(params: &Parameters, logfiles: Vec<File>) {
-    logfiles
-        .into_par_iter()
-        .enumerate()
-        .for_each(|(i, mut logfile)| {
-            let rng = &mut rand::rng();
-            let pm = ProbeMax::from_bpr_max(&BipartiteRegulatorProbing::create_random(
+/// Create the BPR-model of an iteration (random or loaded) and rebin its edges if requested
+fn create_model<R: Rng>(
+    params: &Parameters,
+    iteration: usize,
+    rng: &mut R,
+) -> BipartiteRegulatorProbing {
+    let mut bpr = match params.models.get(iteration) {
+        Some(bpr) => bpr.clone(),
+        None => {
+            let mut bpr = BipartiteRegulatorProbing::create_random(
                 rng,
                 params.na,
                 params.nb,
                 params.vs,
-                params.poisson,
-                params.instances,
-            ));
+                &params.distribution,
+            )
+            .expect("Could not create random BPR-model");
+            bpr.set_correlation(params.correlation);
+            if let Some(max_cost) = params.max_cost {
+                bpr.set_regulator_costs(
+                    (0..params.na)
+                        .map(|_| rng.random_range(1.0..=max_cost))
+                        .collect(),
+                );
+            }
+            bpr
+        }
+    };
 
-            for j in 0..params.instances {
-                let ins = pm.create_instance(j);
-
-                for opt_l in compute_opt_l_values(params.na) {
-                    let res = Result {
-                        na: params.na,
-                        nb: params.nb,
-                        vs: params.vs,
-                        goal: "MAX".to_owned(),
-                        algo: "OPT".to_owned(),
-                        k: params.na,
-                        l: opt_l,
-                        val: ins.get_optimal_value(opt_l),
-                        ins_id: i,
-                        iter_id: j,
-                        time: ins.get_optimal_time(),
-                    };
-                    let _ = writeln!(logfile, "{}", serde_json::to_string(&res).unwrap());
-                }
+    if let Some(noise) = params.noise {
+        bpr.set_noise(noise);
+    }
 
-                for (alg_k, alg_l) in compute_k_l_pairs(params.na) {
-                    let (amp_val, amp_time) = ins.adaptive_policy(alg_k, alg_l);
-                    let res = Result {
-                        na: params.na,
-                        nb: params.nb,
-                        vs: params.vs,
-                        goal: "MAX".to_owned(),
-                        algo: "AMP".to_owned(),
-                        k: alg_k,
-                        l: alg_l,
-                        val: amp_val,
-                        ins_id: i,
-                        iter_id: j,
-                        time: amp_time,
-                    };
-                    let _ = writeln!(logfile, "{}", serde_json::to_string(&res).unwrap());
-
-                    let (namp_val, namp_time) = ins.get_non_adap_value(alg_k, alg_l);
-                    let res = Result {
-                        na: params.na,
-                        nb: params.nb,
-                        vs: params.vs,
-                        goal: "MAX".to_owned(),
-                        algo: "NAMP".to_owned(),
-                        k: alg_k,
-                        l: alg_l,
-                        val: namp_val,
-                        ins_id: i,
-                        iter_id: j,
-                        time: namp_time,
-                    };
-                    let _ = writeln!(logfile, "{}", serde_json::to_string(&res).unwrap());
-                }
-            }
-        });
+    if let Some(bins) = params.bins {
+        let bound = bpr.rebin(bins, &params.binning);
+        eprintln!(
+            "Rebinned edges to at most {} values, expected values change by at most {}",
+            bins, bound
+        );
+    }
+
+    bpr
 }
 
-fn eval_sum(params: &Parameters, logfiles: Vec<File>) {
-    logfiles
-        .into_par_iter()
-        .enumerate()
-        .for_each(|(i, mut logfile)| {
-            let rng = &mut rand::rng();
-            let pm = ProbeMax::from_bpr_sum(&BipartiteRegulatorProbing::create_random(
-                rng,
-                params.na,
-                params.nb,
-                params.vs,
-                params.poisson,
-                params.instances,
-            ));
+/// Save the model of an iteration together with the realizations of its instances if requested
+fn dump_model(params: &Parameters, iteration: usize, bpr: &BipartiteRegulatorProbing) {
+    if let Some(dump) = &params.dump {
+        let mut bpr = bpr.clone();
+        bpr.store_realizations(params.instances);
 
-            for j in 0..params.instances {
-                let ins = pm.create_instance(j);
-
-                for opt_l in compute_opt_l_values(params.na) {
-                    let res = Result {
-                        na: params.na,
-                        nb: params.nb,
-                        vs: params.vs,
-                        goal: "SUM".to_owned(),
-                        algo: "OPT".to_owned(),
-                        k: params.na,
-                        l: opt_l,
-                        val: ins.get_optimal_value(opt_l),
-                        ins_id: i,
-                        iter_id: j,
-                        time: ins.get_optimal_time(),
-                    };
-                    let _ = writeln!(logfile, "{}", serde_json::to_string(&res).unwrap());
-                }
+        let extension = match params.format {
+            StorageFormat::JSON => "json",
+            StorageFormat::BINARY => "bin",
+        };
+        let path = dump.join(format!("{:?}_{}.{}", params.goal, iteration, extension));
 
-                for (alg_k, alg_l) in compute_k_l_pairs(params.na) {
-                    let (amp_val, amp_time) = ins.adaptive_policy(alg_k, alg_l);
-                    let res = Result {
-                        na: params.na,
-                        nb: params.nb,
-                        vs: params.vs,
-                        goal: "SUM".to_owned(),
-                        algo: "AMP".to_owned(),
-                        k: alg_k,
-                        l: alg_l,
-                        val: amp_val,
-                        ins_id: i,
-                        iter_id: j,
-                        time: amp_time,
-                    };
-                    let _ = writeln!(logfile, "{}", serde_json::to_string(&res).unwrap());
-
-                    let (namp_val, namp_time) = ins.get_non_adap_value(alg_k, alg_l);
-                    let res = Result {
-                        na: params.na,
-                        nb: params.nb,
-                        vs: params.vs,
-                        goal: "SUM".to_owned(),
-                        algo: "NAMP".to_owned(),
-                        k: alg_k,
-                        l: alg_l,
-                        val: namp_val,
-                        ins_id: i,
-                        iter_id: j,
-                        time: namp_time,
-                    };
-                    let _ = writeln!(logfile, "{}", serde_json::to_string(&res).unwrap());
-                }
-            }
-        });
+        save_model(&bpr, &path, &params.format).expect("Could not save BPR-model");
+    }
 }
 
-fn eval_cov(params: &Parameters, logfiles: Vec<File>) {
+/// Run all selected policies of the model on every instance of every iteration and log the results
+fn eval<M, F>(params: &Parameters, logfiles: Vec<File>, from_bpr: F)
+where
+    M: ProbingModel,
+    F: Fn(BipartiteRegulatorProbing) -> M + Sync,
+{
+    let seed = params.seed.unwrap();
+
+    // OPT is run for every value of l in the grid
+    let opt_l_values: Vec<usize> = params
+        .grid
+        .iter()
+        .map(|(_, l)| *l)
+        .sorted()
+        .dedup()
+        .collect();
+
+    let options = PolicyOptions {
+        ip_opt: params.ipopt,
+        rounds: params.rounds.clone(),
+    };
+
+    let (_, unavailable) = M::select_policies(&params.algorithm, params.not_opt, &options);
+    for algorithm in unavailable {
+        if algorithm == Algorithm::BAMP && params.rounds.is_empty() {
+            eprintln!("BAMP is only run with --rounds");
+        } else {
+            eprintln!("{:?} is not available for {:?}", algorithm, params.goal);
+        }
+    }
+
     logfiles
         .into_par_iter()
         .enumerate()
         .for_each(|(i, mut logfile)| {
-            let rng = &mut rand::rng();
-            let mut bpr = BipartiteRegulatorProbing::create_random(
-                rng,
-                params.na,
-                params.nb,
-                params.vs,
-                params.poisson,
-                params.instances,
-            );
-
-            for (k, l) in compute_k_l_pairs(params.na) {
-                bpr.compute_namp_cov_policy(k, l);
+            let rng = &mut iteration_rng(seed, i);
+            let mut model = from_bpr(create_model(params, i, rng));
+
+            let (offline, online): (Vec<_>, Vec<_>) =
+                M::select_policies(&params.algorithm, params.not_opt, &options)
+                    .0
+                    .into_iter()
+                    .partition(|policy| policy.is_offline());
+
+            if params.budget.is_empty() && params.edge_budget.is_empty() {
+                for policy in &online {
+                    for &(k, l) in &params.grid {
+                        policy.prepare(&mut model, k, l);
+                    }
+                }
             }
+            dump_model(params, i, model.get_bpr());
+            let noise = Some(model.get_bpr().noise).filter(|s| *s > 0.0);
+            let mass_error = model.max_mass_error();
+
+            let mut log = |policy: &dyn Policy<M>,
+                           k: usize,
+                           l: usize,
+                           (val, time): (f64, f64),
+                           budget: Option<(f64, f64)>,
+                           edges: Option<(usize, usize)>,
+                           j| {
+                let res = Result {
+                    na: params.na,
+                    nb: params.nb,
+                    vs: params.vs,
+                    goal: format!("{:?}", params.goal),
+                    algo: format!("{:?}", policy.algorithm()),
+                    k,
+                    l,
+                    val,
+                    ins_id: i,
+                    iter_id: j,
+                    time,
+                    seed,
+                    rounds: policy.rounds(),
+                    budget: budget.map(|(b, _)| b),
+                    spent: budget.map(|(_, s)| s),
+                    edge_budget: edges.map(|(b, _)| b),
+                    probed_edges: edges.map(|(_, p)| p),
+                    noise,
+                    mass_error,
+                };
+                let _ = writeln!(logfile, "{}", serde_json::to_string(&res).unwrap());
+            };
 
             for j in 0..params.instances {
-                let ins = bpr.create_instance(j);
-
-                for opt_l in compute_opt_l_values(params.na) {
-                    let res = if params.ipopt {
-                        let timer = Instant::now();
-                        let ip_val = bpr::ip::solve_cov_instance(
-                            params.na,
-                            params.nb,
-                            opt_l,
-                            &ins.realizations,
-                        );
-                        let ip_time = timer.elapsed().as_secs_f64();
-
-                        Result {
-                            na: params.na,
-                            nb: params.nb,
-                            vs: params.vs,
-                            goal: "COV".to_owned(),
-                            algo: "OPT".to_owned(),
-                            k: params.na,
-                            l: opt_l,
-                            val: ip_val,
-                            ins_id: i,
-                            iter_id: j,
-                            time: ip_time,
+                let ins = model.create_instance(j);
+
+                for policy in &offline {
+                    for &opt_l in &opt_l_values {
+                        let res = policy.run(&ins, params.na, opt_l);
+                        log(policy.as_ref(), params.na, opt_l, res, None, None, j);
+                    }
+                }
+
+                for &budget in &params.budget {
+                    for &alg_l in &opt_l_values {
+                        for policy in &online {
+                            if let Some((probed, val, time)) =
+                                policy.run_budget(&ins, budget, alg_l)
+                            {
+                                let spent = model.get_bpr().get_probing_cost(&probed);
+                                log(
+                                    policy.as_ref(),
+                                    probed.len(),
+                                    alg_l,
+                                    (val, time),
+                                    Some((budget, spent)),
+                                    None,
+                                    j,
+                                );
+                            }
                         }
-                    } else {
-                        Result {
-                            na: params.na,
-                            nb: params.nb,
-                            vs: params.vs,
-                            goal: "COV".to_owned(),
-                            algo: "OPT".to_owned(),
-                            k: params.na,
-                            l: opt_l,
-                            val: ins.get_opt_cov_value(opt_l),
-                            ins_id: i,
-                            iter_id: j,
-                            time: ins.get_opt_cov_time(),
+                    }
+                }
+
+                for &budget in &params.edge_budget {
+                    for &alg_l in &opt_l_values {
+                        for policy in &online {
+                            if let Some((probed, val, time)) = policy.run_edges(&ins, budget, alg_l)
+                            {
+                                // k is the number of Regulators with at least one probed edge
+                                let k = probed.iter().map(|(a, _)| *a).unique().count();
+                                log(
+                                    policy.as_ref(),
+                                    k,
+                                    alg_l,
+                                    (val, time),
+                                    None,
+                                    Some((budget, probed.len())),
+                                    j,
+                                );
+                            }
                         }
-                    };
-                    let _ = writeln!(logfile, "{}", serde_json::to_string(&res).unwrap());
+                    }
+                }
+
+                if !params.budget.is_empty() || !params.edge_budget.is_empty() {
+                    continue;
                 }
 
-                for (alg_k, alg_l) in compute_k_l_pairs(params.na) {
-                    let (amp_val, amp_time) = ins.adaptive_policy(alg_k, alg_l);
-                    let res = Result {
-                        na: params.na,
-                        nb: params.nb,
-                        vs: params.vs,
-                        goal: "COV".to_owned(),
-                        algo: "AMP".to_owned(),
-                        k: alg_k,
-                        l: alg_l,
-                        val: amp_val,
-                        ins_id: i,
-                        iter_id: j,
-                        time: amp_time,
-                    };
-                    let _ = writeln!(logfile, "{}", serde_json::to_string(&res).unwrap());
-
-                    let timer = Instant::now();
-                    let namp_val = ins.eval_policy(bpr.get_policy(alg_k, alg_l).unwrap(), alg_l);
-                    let res = Result {
-                        na: params.na,
-                        nb: params.nb,
-                        vs: params.vs,
-                        goal: "COV".to_owned(),
-                        algo: "NAMP".to_owned(),
-                        k: alg_k,
-                        l: alg_l,
-                        val: namp_val,
-                        ins_id: i,
-                        iter_id: j,
-                        time: bpr.get_policy_time(alg_k, alg_l).unwrap()
-                            + timer.elapsed().as_secs_f64(),
-                    };
-                    let _ = writeln!(logfile, "{}", serde_json::to_string(&res).unwrap());
-
-                    let timer = Instant::now();
-                    let ext_val = ins.eval_policy(bpr.get_policy(alg_k, alg_k).unwrap(), alg_l);
-                    let res = Result {
-                        na: params.na,
-                        nb: params.nb,
-                        vs: params.vs,
-                        goal: "COV".to_owned(),
-                        algo: "EXT".to_owned(),
-                        k: alg_k,
-                        l: alg_l,
-                        val: ext_val,
-                        ins_id: i,
-                        iter_id: j,
-                        time: bpr.get_policy_time(alg_k, alg_k).unwrap()
-                            + timer.elapsed().as_secs_f64(),
-                    };
-                    let _ = writeln!(logfile, "{}", serde_json::to_string(&res).unwrap());
+                for &(alg_k, alg_l) in &params.grid {
+                    for policy in &online {
+                        let res = policy.run(&ins, alg_k, alg_l);
+                        log(policy.as_ref(), alg_k, alg_l, res, None, None, j);
+                    }
                 }
             }
         });
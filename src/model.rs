@@ -3,33 +3,161 @@ use std::time::Instant;
 use ez_bitset::bitset::*;
 use itertools::Itertools;
 use rand::Rng;
+use rand_pcg::Pcg64;
+use serde_derive::{Deserialize, Serialize};
+use statrs::function::erf::erfc;
 
-use crate::distributions::*;
+use crate::{distributions::*, Binning, DistributionFamily};
 
 pub const NUM_TOP_TUPLES: usize = 10;
 
-#[derive(Debug, Clone)]
+/// Edge `(a, b)` between Regulator a and Position b
+pub type Edge = (usize, usize);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BipartiteRegulatorProbing {
     // Number of Regulators
     pub na: usize,
     // Number of Positions
     pub nb: usize,
-    // Size of Support
+    // Maximum Size of Support of all Edges (edges may have smaller supports)
     pub vs: usize,
-    // Distributions of Edges
+    // Distributions of the Edges of every Regulator
     pub edges: Vec<Vec<WeightedDistribution>>,
+    // Sorted Positions of the Edges of every Regulator, i.e. `edges[a][i]` is edge `(a, positions[a][i])`.
+    // Absent edges are always 0
+    pub positions: Vec<Vec<usize>>,
     // Optional Non-Adaptive COV Policies for given k and l
     pub non_adaptive_cov_policies: Vec<(usize, usize, Vec<usize>, f64)>,
+    // Seed from which the realizations of all instances are drawn
+    pub seed: u64,
+    // Correlation of the edges of a Regulator in a Gaussian copula (0 means independent edges)
+    pub correlation: f64,
+    // Standard deviation of the Gaussian noise of a probe (0 means that probes reveal the exact realizations)
+    pub noise: f64,
+    // Stored edge realizations of the first instances which are used instead of sampling them
+    pub stored_realizations: Vec<Vec<Vec<f64>>>,
+    // Importance of every Position in the Coverage objective (all 1 unless set)
+    pub position_weights: Vec<f64>,
+    // Cost of probing every Regulator (all 1 unless set)
+    pub regulator_costs: Vec<f64>,
+    // Optional constraint on the selected Regulators
+    pub group_constraint: Option<GroupConstraint>,
+}
+
+/// Partition-matroid constraint on the selected Regulators: at most `capacities[g]` Regulators of group `g`
+/// can be selected while Regulators without a group are unconstrained
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupConstraint {
+    /// Group of every Regulator
+    pub groups: Vec<Option<usize>>,
+    /// Maximum number of selected Regulators of every group
+    pub capacities: Vec<usize>,
+}
+
+impl GroupConstraint {
+    pub fn new(groups: Vec<Option<usize>>, capacities: Vec<usize>) -> Self {
+        assert!(
+            groups.iter().flatten().all(|g| *g < capacities.len()),
+            "Every group needs a capacity"
+        );
+        Self { groups, capacities }
+    }
+
+    /// Can Regulator a be selected in addition to the given number of selected Regulators per group
+    #[inline]
+    pub fn allows(&self, counts: &[usize], a: usize) -> bool {
+        self.groups[a].is_none_or(|g| counts[g] < self.capacities[g])
+    }
+
+    /// Count Regulator a as selected
+    #[inline]
+    pub fn add(&self, counts: &mut [usize], a: usize) {
+        if let Some(g) = self.groups[a] {
+            counts[g] += 1;
+        }
+    }
+
+    /// Does a selection of Regulators satisfy the constraint
+    pub fn is_feasible(&self, selection: &[usize]) -> bool {
+        let mut counts = vec![0; self.capacities.len()];
+        selection.iter().all(|a| {
+            let allowed = self.allows(&counts, *a);
+            self.add(&mut counts, *a);
+            allowed
+        })
+    }
 }
 
 impl BipartiteRegulatorProbing {
-    pub fn new(na: usize, nb: usize, vs: usize, edges: Vec<Vec<WeightedDistribution>>) -> Self {
+    /// Create a BPR-model of a complete bipartite graph from the distributions of all edges.
+    /// Every edge can have its own support, `vs` is set to the largest support size.
+    pub fn new(na: usize, nb: usize, edges: Vec<Vec<WeightedDistribution>>, seed: u64) -> Self {
+        assert!(
+            edges.iter().all(|regulator| regulator.len() == nb),
+            "Expected one edge per Position"
+        );
+        let positions = vec![(0..nb).collect(); na];
+        Self::from_edge_lists(na, nb, edges, positions, seed)
+    }
+
+    /// Create a BPR-model of a sparse bipartite graph from the edges `(b, D_ab)` of every Regulator.
+    /// All other edges are always 0.
+    pub fn new_sparse(
+        na: usize,
+        nb: usize,
+        edges: Vec<Vec<(usize, WeightedDistribution)>>,
+        seed: u64,
+    ) -> Self {
+        let (edges, positions) = edges
+            .into_iter()
+            .map(|mut regulator| {
+                regulator.sort_by_key(|(b, _)| *b);
+                assert!(
+                    regulator.iter().all(|(b, _)| *b < nb),
+                    "Position out of range"
+                );
+                assert!(
+                    regulator.windows(2).all(|w| w[0].0 < w[1].0),
+                    "Duplicate edge"
+                );
+                let (positions, edges): (Vec<usize>, Vec<WeightedDistribution>) =
+                    regulator.into_iter().unzip();
+                (edges, positions)
+            })
+            .unzip();
+        Self::from_edge_lists(na, nb, edges, positions, seed)
+    }
+
+    /// Create a BPR-model from the edges of every Regulator and their (sorted) Positions
+    pub(crate) fn from_edge_lists(
+        na: usize,
+        nb: usize,
+        edges: Vec<Vec<WeightedDistribution>>,
+        positions: Vec<Vec<usize>>,
+        seed: u64,
+    ) -> Self {
+        assert_eq!(edges.len(), na, "Expected edges for every Regulator");
+        let vs = edges
+            .iter()
+            .flat_map(|regulator| regulator.iter().map(|d| d.size()))
+            .max()
+            .unwrap_or(0);
+
         Self {
             na,
             nb,
             vs,
             edges,
+            positions,
             non_adaptive_cov_policies: Vec::new(),
+            seed,
+            correlation: 0.0,
+            noise: 0.0,
+            stored_realizations: Vec::new(),
+            position_weights: vec![1.0; nb],
+            regulator_costs: vec![1.0; na],
+            group_constraint: None,
         }
     }
 
@@ -38,36 +166,177 @@ impl BipartiteRegulatorProbing {
         na: usize,
         nb: usize,
         vs: usize,
-        poisson: bool,
-        num_instances: usize,
-    ) -> Self {
+        family: &DistributionFamily,
+    ) -> Result<Self, DistributionError> {
         let edges: Vec<Vec<WeightedDistribution>> = (0..na)
             .map(|_| {
                 (0..nb)
                     .map(|_| {
                         if vs <= 1 {
-                            return WeightedDistribution::new(rng, &[1.0], num_instances);
+                            return WeightedDistribution::try_new(&[1.0]);
                         }
 
-                        let weights = if poisson {
-                            create_poisson_weights(rng, vs)
-                        } else {
-                            create_random_weights(rng, vs)
-                        };
-
-                        WeightedDistribution::new(rng, &weights, num_instances)
+                        create_random_distribution(rng, family, vs)
                     })
                     .collect()
             })
+            .collect::<Result<_, _>>()?;
+
+        let seed = rng.random();
+
+        Ok(Self::new(na, nb, edges, seed))
+    }
+
+    /// Merge groups of Regulators (e.g. motif variants of a TF) into single Regulators whose edges are the
+    /// reductions of the (independent) edges of their group. Every Regulator must be in exactly one group.
+    /// A merged Regulator costs as much as the most expensive Regulator of its group.
+    /// Cached non-adaptive policies, stored realizations and the group constraint are discarded.
+    pub fn merge_regulators(&self, groups: &[Vec<usize>], reduction: &Reduction) -> Self {
+        let mut covered = vec![false; self.na];
+        for a in groups.iter().flatten() {
+            assert!(!covered[*a], "Regulator {} is in more than one group", a);
+            covered[*a] = true;
+        }
+        assert!(
+            covered.into_iter().all(|c| c),
+            "Every Regulator needs a group"
+        );
+
+        // A merged edge exists if any variant has an edge to its Position
+        let edges: Vec<Vec<(usize, WeightedDistribution)>> = groups
+            .iter()
+            .map(|group| match group.as_slice() {
+                [a] => self
+                    .get_regulator_edges(*a)
+                    .map(|(b, d)| (b, d.clone()))
+                    .collect(),
+                _ => group
+                    .iter()
+                    .flat_map(|a| self.positions[*a].iter().copied())
+                    .sorted_unstable()
+                    .dedup()
+                    .map(|b| {
+                        let mut variants: Vec<WeightedDistribution> = group
+                            .iter()
+                            .filter_map(|a| self.get_edge(*a, b).cloned())
+                            .collect();
+                        let zeros = reduction.relevant_zeros(group.len() - variants.len());
+                        variants.extend(std::iter::repeat_n(zero_distribution(), zeros));
+                        (b, reduction.distribution(&variants))
+                    })
+                    .collect(),
+            })
             .collect();
 
-        Self {
-            na,
-            nb,
-            vs,
-            edges,
-            non_adaptive_cov_policies: Vec::new(),
+        let mut merged = Self::new_sparse(groups.len(), self.nb, edges, self.seed);
+        merged.correlation = self.correlation;
+        merged.noise = self.noise;
+        merged.position_weights = self.position_weights.clone();
+        merged.regulator_costs = groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|a| self.regulator_costs[*a])
+                    .fold(0.0, f64::max)
+            })
+            .collect();
+
+        merged
+    }
+
+    /// Map every edge onto at most `bins` support values (see `WeightedDistribution::rebin`).
+    /// With `Binning::UNIFORM` all edges share one grid so reductions stay cheap.
+    /// Cached non-adaptive policies and stored realizations are discarded.
+    ///
+    /// Returns the largest bound `sum_b E[|X_ab - Y_ab|]` over all regulators `a`, which bounds the change
+    /// of the expected value of every MAX- or SUM-box.
+    pub fn rebin(&mut self, bins: usize, binning: &Binning) -> f64 {
+        assert!(bins > 0);
+
+        // Shared lattice spanning the supports of all edges with `bins` points
+        let (min, max) = self
+            .edges
+            .iter()
+            .flatten()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), d| {
+                (min.min(d.value(0)), max.max(d.value(d.size() - 1)))
+            });
+
+        let mut bound: f64 = 0.0;
+        for regulator in self.edges.iter_mut() {
+            let mut regulator_bound = 0.0;
+            for edge in regulator.iter_mut() {
+                let (coarse, error) = match binning {
+                    Binning::UNIFORM if bins > 1 => {
+                        edge.rebin_uniform(min, (max - min) / (bins - 1) as f64)
+                    }
+                    // A single bin is the point mass at the expected value of every edge
+                    _ => edge.rebin_quantile(bins),
+                };
+                *edge = coarse;
+                regulator_bound += error;
+            }
+            bound = bound.max(regulator_bound);
         }
+
+        self.vs = self
+            .edges
+            .iter()
+            .flatten()
+            .map(|d| d.size())
+            .max()
+            .unwrap_or(0);
+        self.non_adaptive_cov_policies.clear();
+        self.stored_realizations.clear();
+
+        bound
+    }
+
+    /// Weight the Positions in the Coverage objective `sum_b w_b * max_a X_ab`.
+    /// Cached non-adaptive policies are discarded as they depend on the weights.
+    pub fn set_position_weights(&mut self, weights: Vec<f64>) {
+        assert_eq!(weights.len(), self.nb, "Expected one weight per Position");
+        assert!(
+            weights.iter().all(|w| w.is_finite() && *w >= 0.0),
+            "Weights must be non-negative"
+        );
+        self.position_weights = weights;
+        self.non_adaptive_cov_policies.clear();
+    }
+
+    /// Get the Weight of Position b
+    #[inline]
+    pub fn get_position_weight(&self, b: usize) -> f64 {
+        self.position_weights[b]
+    }
+
+    /// Get the Weights of all Positions
+    #[inline]
+    pub fn get_position_weights(&self) -> &[f64] {
+        &self.position_weights
+    }
+
+    /// Set the cost of probing every Regulator which is used by the budgeted policies
+    pub fn set_regulator_costs(&mut self, costs: Vec<f64>) {
+        assert_eq!(costs.len(), self.na, "Expected one cost per Regulator");
+        assert!(
+            costs.iter().all(|c| c.is_finite() && *c > 0.0),
+            "Costs must be positive"
+        );
+        self.regulator_costs = costs;
+    }
+
+    /// Get the Cost of probing Regulator a
+    #[inline]
+    pub fn get_regulator_cost(&self, a: usize) -> f64 {
+        self.regulator_costs[a]
+    }
+
+    /// Get the total Cost of probing the given Regulators
+    #[inline]
+    pub fn get_probing_cost(&self, regulators: &[usize]) -> f64 {
+        regulators.iter().map(|a| self.regulator_costs[*a]).sum()
     }
 
     /// Get Number of Regulators
@@ -82,22 +351,258 @@ impl BipartiteRegulatorProbing {
         self.nb
     }
 
-    /// Get Size of Support
+    /// Get the maximum Size of Support
     #[inline]
     pub fn get_vs(&self) -> usize {
         self.vs
     }
 
-    /// Get the Distributions of all incident edges of Regulator a
+    /// Get the Distributions of all incident edges of Regulator a (in the order of `get_positions(a)`)
     #[inline]
     pub fn get_regulator(&self, a: usize) -> &Vec<WeightedDistribution> {
         &self.edges[a]
     }
 
-    /// Get the Distribution of edge (a,b)
+    /// Get the sorted Positions of all incident edges of Regulator a
+    #[inline]
+    pub fn get_positions(&self, a: usize) -> &[usize] {
+        &self.positions[a]
+    }
+
+    /// Get all incident edges `(b, D_ab)` of Regulator a
+    #[inline]
+    pub fn get_regulator_edges(
+        &self,
+        a: usize,
+    ) -> impl Iterator<Item = (usize, &WeightedDistribution)> + '_ {
+        self.positions[a].iter().copied().zip(self.edges[a].iter())
+    }
+
+    /// Get the Distribution of edge (a,b) if it exists
+    #[inline]
+    pub fn get_edge(&self, a: usize, b: usize) -> Option<&WeightedDistribution> {
+        self.positions[a]
+            .binary_search(&b)
+            .ok()
+            .map(|i| &self.edges[a][i])
+    }
+
+    /// Get the number of incident edges of Regulator a
+    #[inline]
+    pub fn get_degree(&self, a: usize) -> usize {
+        self.positions[a].len()
+    }
+
+    /// Get the number of edges of the graph
     #[inline]
-    pub fn get_edge(&self, a: usize, b: usize) -> &WeightedDistribution {
-        &self.edges[a][b]
+    pub fn get_num_edges(&self) -> usize {
+        self.positions.iter().map(|p| p.len()).sum()
+    }
+
+    /// Get the Distribution of the reduction of all edges of Regulator a including its absent zero-edges
+    pub fn reduce_regulator(&self, a: usize, reduction: &Reduction) -> WeightedDistribution {
+        let zeros = reduction.relevant_zeros(self.nb - self.get_degree(a));
+        if zeros == 0 {
+            return reduction.distribution(&self.edges[a]);
+        }
+
+        self.reduce_distributions(a, self.edges[a].clone(), reduction)
+    }
+
+    /// Get the reduction of the realizations of all edges of Regulator a including its absent zero-edges
+    pub fn reduce_realizations(
+        &self,
+        a: usize,
+        mut values: Vec<f64>,
+        reduction: &Reduction,
+    ) -> f64 {
+        let zeros = reduction.relevant_zeros(self.nb - self.get_degree(a));
+        values.extend(std::iter::repeat_n(0.0, zeros));
+        reduction.reduce(values)
+    }
+
+    /// Get the independent random stream of an instance
+    #[inline]
+    pub fn instance_rng(&self, instance_index: usize) -> Pcg64 {
+        Pcg64::new(self.seed as u128, instance_index as u128)
+    }
+
+    /// Correlate the edges of every Regulator via a one-factor Gaussian copula: the realization of edge (a,b)
+    /// is the `Phi(sqrt(rho) * Z_a + sqrt(1 - rho) * E_ab)`-quantile of its distribution where `Z_a` is
+    /// the latent strength of Regulator a and all `Z_a, E_ab` are independent standard normal.
+    ///
+    /// The marginal distribution of every edge is unchanged, so all per-edge expectations (and thus the
+    /// COV policies) stay exact. Distributions of reductions over a Regulator (ProbeMax boxes) assume
+    /// independent edges and become approximations for `rho > 0`.
+    pub fn set_correlation(&mut self, rho: f64) {
+        assert!((0.0..=1.0).contains(&rho), "Correlation must be in [0, 1]");
+        self.correlation = rho;
+    }
+
+    /// Are the edges of every Regulator independent
+    #[inline]
+    pub fn has_independent_edges(&self) -> bool {
+        self.correlation == 0.0
+    }
+
+    /// Draw the edge realizations of an instance (or return them if they are stored).
+    /// The same instance index always yields the same realizations.
+    /// Realizations are aligned with the edges, i.e. `realizations[a][i]` belongs to `edges[a][i]`.
+    pub fn sample_realizations(&self, instance_index: usize) -> Vec<Vec<f64>> {
+        if let Some(realizations) = self.stored_realizations.get(instance_index) {
+            return realizations.clone();
+        }
+
+        let rng = &mut self.instance_rng(instance_index);
+
+        if self.has_independent_edges() {
+            return self
+                .edges
+                .iter()
+                .map(|regulator| regulator.iter().map(|d| d.sample(rng)).collect())
+                .collect();
+        }
+
+        let (shared, own) = (self.correlation.sqrt(), (1.0 - self.correlation).sqrt());
+        self.edges
+            .iter()
+            .map(|regulator| {
+                let strength = standard_normal(rng);
+                regulator
+                    .iter()
+                    .map(|d| {
+                        let x = shared * strength + own * standard_normal(rng);
+                        d.quantile(0.5 * erfc(-x / std::f64::consts::SQRT_2))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Let a probe of edge (a,b) observe `X_ab + N(0, sigma^2)` instead of the realization `X_ab`, e.g. to model the
+    /// variability between replicates of a binding assay. Policies then act on the posterior distributions of the
+    /// probed edges while all values are still evaluated on the (hidden) realizations. The posteriors assume
+    /// independent edges and are approximations for correlated edges.
+    pub fn set_noise(&mut self, sigma: f64) {
+        assert!(
+            sigma.is_finite() && sigma >= 0.0,
+            "Noise must be finite and non-negative"
+        );
+        self.noise = sigma;
+    }
+
+    /// Do probes only reveal noisy observations of the realizations
+    #[inline]
+    pub fn is_noisy(&self) -> bool {
+        self.noise > 0.0
+    }
+
+    /// Get the random stream of the observations of an instance, which is independent of its realizations
+    #[inline]
+    pub fn noise_rng(&self, instance_index: usize) -> Pcg64 {
+        Pcg64::new(self.seed as u128, (1 << 64) | instance_index as u128)
+    }
+
+    /// Draw the noisy observations of all edges of an instance with the given realizations (aligned with the edges).
+    /// The same instance index always yields the same observations.
+    pub fn sample_observations(
+        &self,
+        instance_index: usize,
+        realizations: &[Vec<f64>],
+    ) -> Vec<Vec<f64>> {
+        let rng = &mut self.noise_rng(instance_index);
+        realizations
+            .iter()
+            .map(|regulator| {
+                regulator
+                    .iter()
+                    .map(|x| x + self.noise * standard_normal(rng))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Get the posterior distributions of all edges of Regulator a given their observations (aligned with the edges)
+    pub fn posterior_regulator(&self, a: usize, observations: &[f64]) -> Vec<WeightedDistribution> {
+        self.edges[a]
+            .iter()
+            .zip(observations)
+            .map(|(edge, y)| edge.gaussian_posterior(*y, self.noise))
+            .collect()
+    }
+
+    /// Get the Distribution of the reduction of the given edge distributions of Regulator a including its
+    /// absent zero-edges
+    pub fn reduce_distributions(
+        &self,
+        a: usize,
+        mut edges: Vec<WeightedDistribution>,
+        reduction: &Reduction,
+    ) -> WeightedDistribution {
+        let zeros = reduction.relevant_zeros(self.nb - self.get_degree(a));
+        edges.extend(std::iter::repeat_n(zero_distribution(), zeros));
+        reduction.distribution(&edges)
+    }
+
+    /// Store the edge realizations of the first `instances` instances, e.g. before saving the model
+    pub fn store_realizations(&mut self, instances: usize) {
+        for instance_index in self.stored_realizations.len()..instances {
+            let realizations = self.sample_realizations(instance_index);
+            self.stored_realizations.push(realizations);
+        }
+    }
+
+    /// Constrain the selected Regulators to at most a given number per group.
+    /// Cached non-adaptive policies are discarded as they depend on the constraint.
+    pub fn set_group_constraint(&mut self, constraint: GroupConstraint) {
+        assert_eq!(
+            constraint.groups.len(),
+            self.na,
+            "Expected one group per Regulator"
+        );
+        self.group_constraint = Some(constraint);
+        self.non_adaptive_cov_policies.clear();
+    }
+
+    /// Are the selected Regulators constrained
+    #[inline]
+    pub fn has_group_constraint(&self) -> bool {
+        self.group_constraint.is_some()
+    }
+
+    /// Number of selected Regulators per group of an empty selection
+    #[inline]
+    pub fn new_group_counts(&self) -> Vec<usize> {
+        vec![
+            0;
+            self.group_constraint
+                .as_ref()
+                .map_or(0, |c| c.capacities.len())
+        ]
+    }
+
+    /// Can Regulator a be selected in addition to the given number of selected Regulators per group
+    #[inline]
+    pub fn can_select(&self, counts: &[usize], a: usize) -> bool {
+        self.group_constraint
+            .as_ref()
+            .is_none_or(|c| c.allows(counts, a))
+    }
+
+    /// Count Regulator a as selected
+    #[inline]
+    pub fn select(&self, counts: &mut [usize], a: usize) {
+        if let Some(c) = &self.group_constraint {
+            c.add(counts, a);
+        }
+    }
+
+    /// Does a selection of Regulators satisfy the group constraint
+    #[inline]
+    pub fn is_feasible_selection(&self, selection: &[usize]) -> bool {
+        self.group_constraint
+            .as_ref()
+            .is_none_or(|c| c.is_feasible(selection))
     }
 
     /// Is there already a policy for this (k,l) pair
@@ -167,8 +672,10 @@ impl BipartiteRegulatorProbing {
 #[derive(Debug, Clone)]
 pub struct Instance<'a> {
     bpr: &'a BipartiteRegulatorProbing,
-    pub realizations: Vec<Vec<usize>>,
-    greedy_cov_values: Vec<usize>,
+    pub realizations: Vec<Vec<f64>>,
+    // Posterior expected values of all edges given their noisy observations (empty for exact probes)
+    posterior_means: Vec<Vec<f64>>,
+    greedy_cov_values: Vec<f64>,
     opt_time: f64,
 }
 
@@ -176,31 +683,44 @@ impl<'a> Instance<'a> {
     /// Create an Instance from a BPR model and computes the vector of a GREEDY algorithm for Offline-Cov
     #[inline]
     pub fn new(bpr: &'a BipartiteRegulatorProbing, instance_index: usize) -> Self {
-        let realizations: Vec<Vec<usize>> = (0..bpr.get_na())
-            .map(|a| {
-                (0..bpr.get_nb())
-                    .map(|b| bpr.get_edge(a, b).get_sample(instance_index))
-                    .collect()
-            })
-            .collect();
+        let realizations = bpr.sample_realizations(instance_index);
+
+        let posterior_means = if bpr.is_noisy() {
+            bpr.sample_observations(instance_index, &realizations)
+                .iter()
+                .enumerate()
+                .map(|(a, observations)| {
+                    bpr.posterior_regulator(a, observations)
+                        .iter()
+                        .map(|d| d.expected_value())
+                        .collect()
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
 
         let timer = Instant::now();
 
-        let mut current_values: Vec<usize> = vec![0; bpr.get_nb()];
-        let mut greedy_cov_values: Vec<usize> = Vec::with_capacity(bpr.get_na() + 1);
+        let mut current_values: Vec<f64> = vec![0.0; bpr.get_nb()];
+        let mut greedy_cov_values: Vec<f64> = Vec::with_capacity(bpr.get_na() + 1);
         let mut chosen_regulators = BitSet::new_all_set(bpr.get_na());
+        let mut group_counts = bpr.new_group_counts();
 
-        greedy_cov_values.push(0);
+        greedy_cov_values.push(0.0);
 
         for _ in 0..bpr.get_na() {
-            let (inc, argmax): (usize, usize) = chosen_regulators
+            let best = chosen_regulators
                 .iter()
-                .map(|a| -> (usize, usize) {
+                .filter(|a| bpr.can_select(&group_counts, *a))
+                .map(|a| -> (f64, usize) {
                     (
-                        (0..bpr.get_nb())
-                            .filter_map(|b| {
-                                if realizations[a][b] > current_values[b] {
-                                    Some(realizations[a][b] - current_values[b])
+                        bpr.get_positions(a)
+                            .iter()
+                            .zip(&realizations[a])
+                            .filter_map(|(&b, &x)| {
+                                if x > current_values[b] {
+                                    Some(bpr.get_position_weight(b) * (x - current_values[b]))
                                 } else {
                                     None
                                 }
@@ -209,21 +729,31 @@ impl<'a> Instance<'a> {
                         a,
                     )
                 })
-                .max()
-                .unwrap();
+                .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap());
+
+            // No more Regulators can be selected: the value stays the same for larger l
+            let (inc, argmax) = match best {
+                Some(best) => best,
+                None => {
+                    greedy_cov_values.push(*greedy_cov_values.last().unwrap());
+                    continue;
+                }
+            };
 
             greedy_cov_values.push(*greedy_cov_values.last().unwrap() + inc);
             chosen_regulators.unset_bit(argmax);
-            (0..bpr.get_nb()).for_each(|b| {
-                if realizations[argmax][b] > current_values[b] {
-                    current_values[b] = realizations[argmax][b];
+            bpr.select(&mut group_counts, argmax);
+            for (&b, &x) in bpr.get_positions(argmax).iter().zip(&realizations[argmax]) {
+                if x > current_values[b] {
+                    current_values[b] = x;
                 }
-            })
+            }
         }
 
         Self {
             bpr,
             realizations,
+            posterior_means,
             greedy_cov_values,
             opt_time: timer.elapsed().as_secs_f64(),
         }
@@ -235,14 +765,68 @@ impl<'a> Instance<'a> {
         self.bpr
     }
 
-    /// Get an Edge-Realization
+    /// Get an Edge-Realization (0 if the edge does not exist)
     #[inline]
-    pub fn get_realization(&self, a: usize, b: usize) -> usize {
-        self.realizations[a][b]
+    pub fn get_realization(&self, a: usize, b: usize) -> f64 {
+        self.bpr.positions[a]
+            .binary_search(&b)
+            .map_or(0.0, |i| self.realizations[a][i])
     }
 
+    /// Get the Realizations `(b, X_ab)` of all incident edges of Regulator a
     #[inline]
-    pub fn get_opt_cov_value(&self, l: usize) -> usize {
+    pub fn get_regulator_realizations(&self, a: usize) -> impl Iterator<Item = (usize, f64)> + '_ {
+        self.bpr.positions[a]
+            .iter()
+            .copied()
+            .zip(self.realizations[a].iter().copied())
+    }
+
+    /// Get the values of the edges of Regulator a revealed by probing it (aligned with `get_positions`):
+    /// the realizations for exact probes and the posterior expected values for noisy probes
+    #[inline]
+    pub fn get_estimates(&self, a: usize) -> &[f64] {
+        if self.bpr.is_noisy() {
+            &self.posterior_means[a]
+        } else {
+            &self.realizations[a]
+        }
+    }
+
+    /// Get the estimates `(b, E[X_ab | probe])` of all incident edges of Regulator a (see `get_estimates`)
+    #[inline]
+    pub fn get_regulator_estimates(&self, a: usize) -> impl Iterator<Item = (usize, f64)> + '_ {
+        self.bpr.positions[a]
+            .iter()
+            .copied()
+            .zip(self.get_estimates(a).iter().copied())
+    }
+
+    /// Get the weighted coverage `sum_b w_b * max_a X_ab` of the given Regulators.
+    /// `values` must be all 0 with one entry per Position and is reset afterwards.
+    fn coverage(&self, regulators: impl Iterator<Item = usize>, values: &mut [f64]) -> f64 {
+        let mut covered: Vec<usize> = Vec::new();
+        for a in regulators {
+            for (b, x) in self.get_regulator_realizations(a) {
+                if x > values[b] {
+                    if values[b] == 0.0 {
+                        covered.push(b);
+                    }
+                    values[b] = x;
+                }
+            }
+        }
+
+        // Sum in the order of the Positions so that the value does not depend on the order of `regulators`
+        covered.sort_unstable();
+        covered
+            .into_iter()
+            .map(|b| self.bpr.get_position_weight(b) * std::mem::take(&mut values[b]))
+            .sum()
+    }
+
+    #[inline]
+    pub fn get_opt_cov_value(&self, l: usize) -> f64 {
         self.greedy_cov_values[l]
     }
 
@@ -252,50 +836,57 @@ impl<'a> Instance<'a> {
     }
 
     #[inline]
-    pub fn eval_policy(&self, policy: &[usize], l: usize) -> usize {
-        if policy.len() == l {
-            return (0..self.bpr.get_nb())
-                .map(|b| {
-                    policy
-                        .iter()
-                        .map(|a| self.realizations[*a][b])
-                        .max()
-                        .unwrap_or(0)
-                })
-                .sum();
+    pub fn eval_policy(&self, policy: &[usize], l: usize) -> f64 {
+        if policy.len() == l && !self.bpr.has_group_constraint() {
+            return self.coverage(policy.iter().copied(), &mut vec![0.0; self.bpr.get_nb()]);
         }
 
-        let mut current_values: Vec<usize> = vec![0; self.bpr.get_nb()];
-        let mut greedy_value = 0usize;
+        // The selection can only rely on the posteriors of the probed edges but is scored on their realizations
+        if self.bpr.is_noisy() {
+            let selection = self.select_by_estimates(policy, l);
+            return self.coverage(selection.into_iter(), &mut vec![0.0; self.bpr.get_nb()]);
+        }
+
+        let mut current_values: Vec<f64> = vec![0.0; self.bpr.get_nb()];
+        let mut greedy_value = 0.0f64;
+        // Positions in `policy` of the regulators not chosen yet
         let mut chosen_regulators = BitSet::new_all_set(policy.len());
+        let mut group_counts = self.bpr.new_group_counts();
 
-        for _ in 0..l {
-            let (inc, argmax): (usize, usize) = chosen_regulators
+        for _ in 0..l.min(policy.len()) {
+            let best = chosen_regulators
                 .iter()
-                .map(|a| -> (usize, usize) {
+                .filter(|i| self.bpr.can_select(&group_counts, policy[*i]))
+                .map(|i| -> (f64, usize) {
                     (
-                        (0..self.bpr.get_nb())
-                            .filter_map(|b| {
-                                if self.realizations[a][b] > current_values[b] {
-                                    Some(self.realizations[a][b] - current_values[b])
+                        self.get_regulator_realizations(policy[i])
+                            .filter_map(|(b, x)| {
+                                if x > current_values[b] {
+                                    Some(self.bpr.get_position_weight(b) * (x - current_values[b]))
                                 } else {
                                     None
                                 }
                             })
                             .sum(),
-                        a,
+                        i,
                     )
                 })
-                .max()
-                .unwrap();
+                .max_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap());
+
+            let (inc, argmax) = match best {
+                Some(best) => best,
+                None => break,
+            };
 
             greedy_value += inc;
             chosen_regulators.unset_bit(argmax);
-            (0..self.bpr.get_nb()).for_each(|b| {
-                if self.realizations[argmax][b] > current_values[b] {
-                    current_values[b] = self.realizations[argmax][b];
+            let argmax = policy[argmax];
+            self.bpr.select(&mut group_counts, argmax);
+            for (b, x) in self.get_regulator_realizations(argmax) {
+                if x > current_values[b] {
+                    current_values[b] = x;
                 }
-            })
+            }
         }
 
         greedy_value
@@ -304,20 +895,18 @@ impl<'a> Instance<'a> {
     pub fn find_top_tuples<const NUM: usize>(
         &self,
         policy: &[usize],
-    ) -> [([usize; NUM], usize); NUM_TOP_TUPLES] {
+    ) -> [([usize; NUM], f64); NUM_TOP_TUPLES] {
         assert!(policy.len() > NUM);
 
-        let mut res = [([0; NUM], 0); NUM_TOP_TUPLES];
+        let mut res = [([0; NUM], 0.0); NUM_TOP_TUPLES];
+        let mut values = vec![0.0; self.bpr.get_nb()];
 
         for regs in (0..policy.len()).array_combinations::<NUM>() {
-            let val: usize = (0..self.bpr.get_nb())
-                .map(|b| {
-                    regs.iter()
-                        .map(|a| self.realizations[policy[*a]][b])
-                        .max()
-                        .unwrap_or(0)
-                })
-                .sum();
+            if !self.bpr.is_feasible_selection(&regs.map(|a| policy[a])) {
+                continue;
+            }
+
+            let val = self.coverage(regs.iter().map(|a| policy[*a]), &mut values);
 
             if val > res[0].1 {
                 insert_in_place(&mut res, (regs.map(|a| policy[a]), val), 0);
@@ -334,38 +923,56 @@ impl<'a> Instance<'a> {
         res
     }
 
-    pub fn top_opt_tuples<const NUM: usize>(&self) -> [([usize; NUM], usize); NUM_TOP_TUPLES] {
+    pub fn top_opt_tuples<const NUM: usize>(&self) -> [([usize; NUM], f64); NUM_TOP_TUPLES] {
         let all_regulators = (0..self.bpr.get_na()).collect_vec();
         self.find_top_tuples(&all_regulators)
     }
 }
 
+/// Distribution of an absent edge, which is always 0
+fn zero_distribution() -> WeightedDistribution {
+    WeightedDistribution::try_new(&[1.0]).unwrap()
+}
+
 #[inline(always)]
+/// Draws a standard normal value using the Box-Muller transform
+fn standard_normal<R: Rng>(rng: &mut R) -> f64 {
+    let u: f64 = 1.0 - rng.random::<f64>();
+    let v: f64 = rng.random();
+    (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+}
+
 fn insert_in_place<T>(array: &mut [T], value: T, index: usize) {
     array[index..].rotate_right(1);
     array[index] = value;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProbeMax {
     /// Number of Boxes
     n: usize,
-    /// Size of Support
+    /// Maximum Size of Support of all Boxes
     v: usize,
     /// Boxes
     boxes: Vec<WeightedDistribution>,
     /// Non-Adaptive Policy
     non_adaptive_policy: (Vec<usize>, f64),
+    /// Underlying BPR-model whose edge realizations are reduced
+    bpr: BipartiteRegulatorProbing,
+    /// Reduction used on the edges of a regulator
+    reduction: Reduction,
 }
 
 impl ProbeMax {
-    /// Create a ProbeMax Instance using a Max-Reduction
-    pub fn from_bpr_max(bpr: &BipartiteRegulatorProbing) -> Self {
+    /// Create a ProbeMax Instance whose boxes are the reductions of the edges of each regulator.
+    /// The boxes assume independent edges; for correlated edges they only approximate the
+    /// distributions of the box realizations (see `has_exact_boxes`).
+    pub fn from_bpr(bpr: BipartiteRegulatorProbing, reduction: Reduction) -> Self {
         let n = bpr.get_na();
-        let v = bpr.get_vs();
         let boxes: Vec<WeightedDistribution> = (0..n)
-            .map(|i| WeightedDistribution::max_distribution(bpr.get_regulator(i)))
+            .map(|i| bpr.reduce_regulator(i, &reduction))
             .collect();
+        let v = boxes.iter().map(|b| b.size()).max().unwrap_or(0);
 
         let non_adaptive_policy = ProbeMax::compute_namp_policy(&boxes);
 
@@ -374,25 +981,47 @@ impl ProbeMax {
             v,
             boxes,
             non_adaptive_policy,
+            bpr,
+            reduction,
         }
     }
 
+    /// Create a ProbeMax Instance using a Max-Reduction
+    pub fn from_bpr_max(bpr: BipartiteRegulatorProbing) -> Self {
+        Self::from_bpr(bpr, Reduction::MAX)
+    }
+
     /// Create a ProbeMax Instance using a Sum-Reduction
-    pub fn from_bpr_sum(bpr: &BipartiteRegulatorProbing) -> Self {
-        let n = bpr.get_na();
-        let v = (bpr.get_vs() - 1) * bpr.get_nb() + 1;
-        let boxes: Vec<WeightedDistribution> = (0..n)
-            .map(|i| WeightedDistribution::sum_distribution(bpr.get_regulator(i)))
-            .collect();
+    pub fn from_bpr_sum(bpr: BipartiteRegulatorProbing) -> Self {
+        Self::from_bpr(bpr, Reduction::SUM)
+    }
 
-        let non_adaptive_policy = ProbeMax::compute_namp_policy(&boxes);
+    /// Draw the box realizations of an instance by reducing the edge realizations of the
+    /// underlying BPR-model. Thus BPR- and ProbeMax-Instances with the same index share their edge draws.
+    pub fn sample_realizations(&self, instance_index: usize) -> Vec<f64> {
+        self.reduce_realizations(self.bpr.sample_realizations(instance_index))
+    }
 
-        Self {
-            n,
-            v,
-            boxes,
-            non_adaptive_policy,
-        }
+    /// Reduce the edge realizations of every regulator to the realization of its box
+    fn reduce_realizations(&self, edge_realizations: Vec<Vec<f64>>) -> Vec<f64> {
+        edge_realizations
+            .into_iter()
+            .enumerate()
+            .map(|(i, edges)| self.bpr.reduce_realizations(i, edges, &self.reduction))
+            .collect()
+    }
+
+    /// Are the box distributions the exact distributions of the box realizations,
+    /// i.e. are the edges of every regulator independent
+    #[inline]
+    pub fn has_exact_boxes(&self) -> bool {
+        self.bpr.has_independent_edges()
+    }
+
+    /// Get the Reduction used on the edges of a regulator
+    #[inline]
+    pub fn get_reduction(&self) -> Reduction {
+        self.reduction
     }
 
     /// Get the number of Boxes
@@ -419,6 +1048,18 @@ impl ProbeMax {
         &self.boxes[i]
     }
 
+    /// Get the total mass error of every derived box distribution (see `WeightedDistribution::mass_error`)
+    #[inline]
+    pub fn get_mass_errors(&self) -> Vec<f64> {
+        self.boxes.iter().map(|b| b.mass_error()).collect()
+    }
+
+    /// Get the underlying BPR-model
+    #[inline]
+    pub fn get_model(&self) -> &BipartiteRegulatorProbing {
+        &self.bpr
+    }
+
     /// Get the Non-Adaptive Policy
     #[inline]
     pub fn get_policy(&self) -> &Vec<usize> {
@@ -443,9 +1084,11 @@ pub struct ProbeMaxInstance<'a> {
     /// Reference to ProbeMax
     pm: &'a ProbeMax,
     /// Realizations of Boxes
-    realizations: Vec<usize>,
+    realizations: Vec<f64>,
+    /// Posterior expected values of the Boxes given the noisy observations of their edges (empty for exact probes)
+    posterior_means: Vec<f64>,
     /// Cumulative optimal value of Boxes
-    cum_opt_realizations: Vec<usize>,
+    cum_opt_realizations: Vec<f64>,
     /// Time taken for computation of optimal values
     opt_time: f64,
 }
@@ -454,18 +1097,35 @@ impl<'a> ProbeMaxInstance<'a> {
     /// Create an Instance from a BPR model
     #[inline]
     pub fn new(pm: &'a ProbeMax, instance_index: usize) -> Self {
-        let realizations: Vec<usize> = (0..pm.get_n())
-            .map(|i| pm.get_box(i).get_sample(instance_index))
-            .collect();
+        let bpr = pm.get_model();
+        let (realizations, posterior_means) = if bpr.is_noisy() {
+            let edge_realizations = bpr.sample_realizations(instance_index);
+            let posterior_means = bpr
+                .sample_observations(instance_index, &edge_realizations)
+                .iter()
+                .enumerate()
+                .map(|(i, observations)| {
+                    bpr.reduce_distributions(
+                        i,
+                        bpr.posterior_regulator(i, observations),
+                        &pm.reduction,
+                    )
+                    .expected_value()
+                })
+                .collect();
+            (pm.reduce_realizations(edge_realizations), posterior_means)
+        } else {
+            (pm.sample_realizations(instance_index), Vec::new())
+        };
 
         let timer = Instant::now();
 
         // Sorted Realizations based on value
         let mut sorted_realizations = realizations.clone();
-        sorted_realizations.sort_by(|a, b| b.cmp(a));
+        sorted_realizations.sort_by(|a, b| b.total_cmp(a));
 
         // Cumulative optimal values
-        let mut cum_opt_realizations: Vec<usize> = Vec::with_capacity(sorted_realizations.len());
+        let mut cum_opt_realizations: Vec<f64> = Vec::with_capacity(sorted_realizations.len());
         cum_opt_realizations.push(sorted_realizations[0]);
         for i in 1..sorted_realizations.len() {
             cum_opt_realizations.push(cum_opt_realizations[i - 1] + sorted_realizations[i]);
@@ -474,6 +1134,7 @@ impl<'a> ProbeMaxInstance<'a> {
         Self {
             pm,
             realizations,
+            posterior_means,
             cum_opt_realizations,
             opt_time: timer.elapsed().as_secs_f64(),
         }
@@ -487,13 +1148,24 @@ impl<'a> ProbeMaxInstance<'a> {
 
     /// Get the realization of a single box
     #[inline]
-    pub fn get_realization(&self, i: usize) -> usize {
+    pub fn get_realization(&self, i: usize) -> f64 {
         self.realizations[i]
     }
 
+    /// Get the value of a single box revealed by probing it: its realization for exact probes and
+    /// its posterior expected value for noisy probes
+    #[inline]
+    pub fn get_estimate(&self, i: usize) -> f64 {
+        if self.pm.get_model().is_noisy() {
+            self.posterior_means[i]
+        } else {
+            self.realizations[i]
+        }
+    }
+
     /// Get the optimal offline value for l boxes
     #[inline]
-    pub fn get_optimal_value(&self, l: usize) -> usize {
+    pub fn get_optimal_value(&self, l: usize) -> f64 {
         self.cum_opt_realizations[l - 1]
     }
 
@@ -503,23 +1175,347 @@ impl<'a> ProbeMaxInstance<'a> {
         self.opt_time
     }
 
+    /// Get the value of the l best boxes among the probed ones. For noisy probes, the l boxes with the
+    /// best estimates are selected and scored on their realizations.
+    #[inline]
+    pub fn eval_policy(&self, policy: &[usize], l: usize) -> f64 {
+        if self.pm.get_model().is_noisy() {
+            let mut probed_boxes = policy.to_vec();
+            probed_boxes.sort_by(|a, b| self.get_estimate(*b).total_cmp(&self.get_estimate(*a)));
+            return probed_boxes
+                .into_iter()
+                .take(l)
+                .map(|i| self.realizations[i])
+                .sum();
+        }
+
+        let mut probed_realizations: Vec<f64> =
+            policy.iter().map(|i| self.realizations[*i]).collect();
+        probed_realizations.sort_by(|a, b| b.total_cmp(a));
+        probed_realizations.into_iter().take(l).sum()
+    }
+
     /// Get the value of the non-adaptive policy
     #[inline]
-    pub fn get_non_adap_value(&self, k: usize, l: usize) -> (usize, f64) {
+    pub fn get_non_adap_value(&self, k: usize, l: usize) -> (f64, f64) {
         let timer = Instant::now();
 
-        let mut non_adap_realizations: Vec<usize> = self
-            .pm
-            .get_policy()
-            .iter()
-            .take(k)
-            .map(|i| self.realizations[*i])
-            .collect();
-        non_adap_realizations.sort_by(|a, b| b.cmp(a));
+        let policy = &self.pm.get_policy()[..k];
 
         (
-            non_adap_realizations.into_iter().take(l).sum(),
+            self.eval_policy(policy, l),
             self.pm.get_policy_time() + timer.elapsed().as_secs_f64(),
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn rebin_model_onto_shared_lattice() {
+        let dist =
+            |values: &[f64]| WeightedDistribution::with_support(values, &vec![1.0; values.len()]);
+        let edges = vec![
+            vec![dist(&[-10.0, -2.0, 3.0]), dist(&[0.0, 1.0, 2.0, 10.0])],
+            vec![dist(&[-7.0, 5.0, 6.0, 7.0, 8.0]), dist(&[4.0])],
+        ];
+        let bpr = BipartiteRegulatorProbing::new(2, 2, edges, 0);
+
+        for bins in 1..=6 {
+            for binning in [Binning::UNIFORM, Binning::QUANTILE] {
+                let mut coarse = bpr.clone();
+                let bound = coarse.rebin(bins, &binning);
+
+                assert!(coarse.edges.iter().flatten().all(|d| d.size() <= bins));
+                for a in 0..2 {
+                    for reduction in [Reduction::MAX, Reduction::SUM] {
+                        let before = bpr.reduce_regulator(a, &reduction).expected_value();
+                        let after = coarse.reduce_regulator(a, &reduction).expected_value();
+                        assert!((before - after).abs() <= bound + 1e-12);
+                    }
+                }
+            }
+        }
+
+        // All values lie on the lattice `-10 + 4 * i` spanning all edges with 6 points
+        let mut coarse = bpr.clone();
+        coarse.rebin(6, &Binning::UNIFORM);
+        for d in coarse.edges.iter().flatten() {
+            for v in d.get_values() {
+                let i = (v + 10.0) / 4.0;
+                assert!(crate::is_close(i, i.round()) && (0.0..=5.0).contains(&i));
+            }
+        }
+    }
+
+    #[test]
+    fn sparse_and_dense_models_agree() {
+        let (na, nb, k) = (8, 10, 5);
+        let rng = &mut Pcg64::seed_from_u64(22);
+
+        // Every edge exists with probability 1/2, absent edges are explicit zero edges in the dense model
+        let edges: Vec<Vec<(usize, WeightedDistribution)>> = (0..na)
+            .map(|_| {
+                let mut regulator = Vec::new();
+                for b in 0..nb {
+                    if rng.random_bool(0.5) {
+                        let dist = create_random_distribution(rng, &DistributionFamily::UNIFORM, 5);
+                        regulator.push((b, dist.unwrap()));
+                    }
+                }
+                regulator
+            })
+            .collect();
+        let dense_edges: Vec<Vec<WeightedDistribution>> = edges
+            .iter()
+            .map(|regulator| {
+                let mut row = vec![zero_distribution(); nb];
+                for (b, dist) in regulator {
+                    row[*b] = dist.clone();
+                }
+                row
+            })
+            .collect();
+
+        let mut sparse = BipartiteRegulatorProbing::new_sparse(na, nb, edges, 7);
+        let mut dense = BipartiteRegulatorProbing::new(na, nb, dense_edges, 7);
+
+        // The zero edges would consume random draws, so both models use the realizations of the sparse model
+        let instances = 4;
+        sparse.store_realizations(instances);
+        dense.stored_realizations = sparse
+            .stored_realizations
+            .iter()
+            .map(|realizations| {
+                (0..na)
+                    .map(|a| {
+                        let mut row = vec![0.0; nb];
+                        for (&b, &x) in sparse.positions[a].iter().zip(&realizations[a]) {
+                            row[b] = x;
+                        }
+                        row
+                    })
+                    .collect()
+            })
+            .collect();
+
+        for l in 1..=k {
+            sparse.compute_namp_cov_policy(k, l);
+            dense.compute_namp_cov_policy(k, l);
+            assert_eq!(sparse.get_policy(k, l), dense.get_policy(k, l));
+        }
+
+        for instance_index in 0..instances {
+            let sparse_instance = sparse.create_instance(instance_index);
+            let dense_instance = dense.create_instance(instance_index);
+            for l in 1..=k {
+                assert!(crate::is_close(
+                    sparse_instance.get_opt_cov_value(l),
+                    dense_instance.get_opt_cov_value(l)
+                ));
+
+                let namp = sparse.get_policy(k, l).unwrap();
+                assert!(crate::is_close(
+                    sparse_instance.eval_policy(namp, l),
+                    dense_instance.eval_policy(namp, l)
+                ));
+
+                let amp = sparse_instance.adaptive_policy_regulators(k, l);
+                assert_eq!(amp, dense_instance.adaptive_policy_regulators(k, l));
+                assert!(crate::is_close(
+                    sparse_instance.eval_policy(&amp, l),
+                    dense_instance.eval_policy(&amp, l)
+                ));
+            }
+        }
+    }
+
+    /// Random model whose probes have the given noise
+    fn noisy_model(noise: Option<f64>) -> BipartiteRegulatorProbing {
+        let rng = &mut Pcg64::seed_from_u64(25);
+        let mut bpr =
+            BipartiteRegulatorProbing::create_random(rng, 8, 6, 5, &DistributionFamily::UNIFORM)
+                .unwrap();
+        if let Some(noise) = noise {
+            bpr.set_noise(noise);
+        }
+        bpr
+    }
+
+    #[test]
+    fn noise_free_probes_match_exact_probes() {
+        let exact = noisy_model(None);
+        let zero_noise = noisy_model(Some(0.0));
+        let tiny_noise = noisy_model(Some(1e-6));
+        let policy = [5, 1, 7, 0, 3];
+
+        for instance_index in 0..5 {
+            let exact = exact.create_instance(instance_index);
+            let zero_noise = zero_noise.create_instance(instance_index);
+            let tiny_noise = tiny_noise.create_instance(instance_index);
+            for l in 1..=policy.len() {
+                let value = exact.eval_policy(&policy, l);
+                assert_eq!(value, zero_noise.eval_policy(&policy, l));
+                // The posteriors concentrate on the realizations, so the noisy path selects the same regulators
+                assert!(crate::is_close(value, tiny_noise.eval_policy(&policy, l)));
+            }
+        }
+    }
+
+    #[test]
+    fn noisy_probes_select_on_estimates_and_score_on_realizations() {
+        let noisy = noisy_model(Some(2.0));
+        let exact = noisy_model(None);
+        let policy = [0, 2, 4, 6, 7];
+        let l = 2;
+
+        let coverage = |instance: &Instance, selection: &[usize], estimates: bool| -> f64 {
+            (0..noisy.get_nb())
+                .map(|b| {
+                    selection
+                        .iter()
+                        .map(|&a| match noisy.get_positions(a).binary_search(&b) {
+                            Ok(i) if estimates => instance.get_estimates(a)[i],
+                            _ => instance.get_realization(a, b),
+                        })
+                        .fold(0.0, f64::max)
+                        * noisy.get_position_weight(b)
+                })
+                .sum()
+        };
+
+        let mut differs = 0;
+        for instance_index in 0..20 {
+            let noisy_instance = noisy.create_instance(instance_index);
+            let exact_instance = exact.create_instance(instance_index);
+
+            // Both instances share the realizations but only the noisy one has posterior estimates
+            for a in 0..noisy.get_na() {
+                for b in 0..noisy.get_nb() {
+                    assert_eq!(
+                        noisy_instance.get_realization(a, b),
+                        exact_instance.get_realization(a, b)
+                    );
+                }
+            }
+
+            let selection = noisy_instance.select_by_estimates(&policy, l);
+            assert!(selection.len() == l && selection.iter().all(|a| policy.contains(a)));
+
+            let value = noisy_instance.eval_policy(&policy, l);
+            assert!(crate::is_close(
+                value,
+                coverage(&noisy_instance, &selection, false)
+            ));
+            assert!(value <= coverage(&exact_instance, &policy, false) + 1e-9);
+
+            if selection != exact_instance.select_by_estimates(&policy, l) {
+                differs += 1;
+                assert!(!crate::is_close(
+                    value,
+                    coverage(&noisy_instance, &selection, true)
+                ));
+            }
+        }
+        assert!(differs > 0);
+    }
+
+    #[test]
+    fn max_and_sum_boxes_share_edge_realizations() {
+        let rng = &mut Pcg64::seed_from_u64(2);
+        let dense =
+            BipartiteRegulatorProbing::create_random(rng, 5, 4, 6, &DistributionFamily::BINOMIAL)
+                .unwrap();
+        let edges = (0..5)
+            .map(|a| (a..4).map(|b| (b, dense.edges[a][b].clone())).collect())
+            .collect();
+        let sparse = BipartiteRegulatorProbing::new_sparse(5, 4, edges, 9);
+
+        for bpr in [dense, sparse] {
+            let pm_max = ProbeMax::from_bpr_max(bpr.clone());
+            let pm_sum = ProbeMax::from_bpr_sum(bpr.clone());
+
+            for instance_index in 0..5 {
+                let instance = bpr.create_instance(instance_index);
+                let max_instance = pm_max.create_instance(instance_index);
+                let sum_instance = pm_sum.create_instance(instance_index);
+
+                for a in 0..bpr.get_na() {
+                    let edges: Vec<f64> = (0..bpr.get_nb())
+                        .map(|b| instance.get_realization(a, b))
+                        .collect();
+                    let max = edges.iter().copied().fold(0.0, f64::max);
+                    let sum: f64 = edges.iter().sum();
+                    assert_eq!(max_instance.get_realization(a), max);
+                    assert!(crate::is_close(sum_instance.get_realization(a), sum));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn copula_keeps_marginals_and_correlates_regulator_edges() {
+        let edges = vec![
+            vec![
+                WeightedDistribution::binomial(9, 0.5).unwrap(),
+                WeightedDistribution::with_support(&[0.0, 1.0, 8.0], &[0.5, 0.3, 0.2]),
+            ],
+            vec![
+                WeightedDistribution::poisson(3.0, 12).unwrap(),
+                WeightedDistribution::bernoulli_scaled(0.3, 5.0).unwrap(),
+            ],
+        ];
+        let independent = BipartiteRegulatorProbing::new(2, 2, edges, 13);
+        let mut correlated = independent.clone();
+        correlated.set_correlation(0.9);
+        let mut zero = independent.clone();
+        zero.set_correlation(0.0);
+
+        let instances = 4000;
+        let samples: Vec<Vec<Vec<f64>>> = (0..instances)
+            .map(|i| correlated.sample_realizations(i))
+            .collect();
+        let edge = |a: usize, i: usize| -> Vec<f64> { samples.iter().map(|s| s[a][i]).collect() };
+        let mean = |xs: &[f64]| xs.iter().sum::<f64>() / xs.len() as f64;
+        let correlation = |xs: &[f64], ys: &[f64]| {
+            let (mx, my) = (mean(xs), mean(ys));
+            let cov: f64 = xs.iter().zip(ys).map(|(x, y)| (x - mx) * (y - my)).sum();
+            let vx: f64 = xs.iter().map(|x| (x - mx).powi(2)).sum();
+            let vy: f64 = ys.iter().map(|y| (y - my).powi(2)).sum();
+            cov / (vx * vy).sqrt()
+        };
+
+        // Every marginal is unchanged: the sample mean lies within 5 standard errors of the expectation
+        for a in 0..2 {
+            for i in 0..2 {
+                let dist = &correlated.edges[a][i];
+                let error = (dist.variance() / instances as f64).sqrt();
+                let xs = edge(a, i);
+                assert!(
+                    (mean(&xs) - dist.expected_value()).abs() < 5.0 * error,
+                    "Edge ({}, {})",
+                    a,
+                    i
+                );
+            }
+        }
+
+        // Edges of a regulator are positively correlated, edges of different regulators are not
+        assert!(correlation(&edge(0, 0), &edge(0, 1)) > 0.5);
+        assert!(correlation(&edge(1, 0), &edge(1, 1)) > 0.3);
+        assert!(correlation(&edge(0, 0), &edge(1, 0)).abs() < 0.1);
+
+        // Correlation 0 takes the independent path
+        assert!(zero.has_independent_edges());
+        for i in 0..50 {
+            assert_eq!(
+                zero.sample_realizations(i),
+                independent.sample_realizations(i)
+            );
+        }
+    }
+}
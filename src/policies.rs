@@ -0,0 +1,585 @@
+//! # Policies
+//!
+//! Common interface of all probing policies on ProbeMax- and BPR-models and the registry of all policies
+//! keyed by `Algorithm`. A new algorithm only needs a variant in `Algorithm` and an entry in
+//! `ProbingModel::policy` to be run by both binaries. An algorithm can have several policies, e.g. `BAMP` has
+//! one for every number of rounds.
+
+use std::time::Instant;
+
+use crate::{
+    ip::solve_cov_instance,
+    model::{BipartiteRegulatorProbing, Edge, Instance, ProbeMax, ProbeMaxInstance},
+    Algorithm,
+};
+
+/// Options of policies which are chosen by the user
+#[derive(Debug, Clone, Default)]
+pub struct PolicyOptions {
+    /// Use the IP-Formulation instead when computing OPT (for Coverage only)
+    pub ip_opt: bool,
+    /// Numbers of rounds of the batched policies (one `BAMP`-policy each)
+    pub rounds: Vec<usize>,
+}
+
+/// Instance on which the selection of probed regulators can be evaluated
+pub trait ProbingInstance {
+    /// Value of selecting `l` of the probed regulators (greedily for Coverage)
+    fn eval_policy(&self, probed: &[usize], l: usize) -> f64;
+}
+
+/// Model from which instances are created and on which policies are run
+pub trait ProbingModel: Sized {
+    type Instance<'a>: ProbingInstance
+    where
+        Self: 'a;
+
+    /// Get the underlying BPR-model
+    fn get_bpr(&self) -> &BipartiteRegulatorProbing;
+
+    /// Create an Instance
+    fn create_instance(&self, instance_index: usize) -> Self::Instance<'_>;
+
+    /// Largest mass error of all box distributions derived from the edges (`None` if no distributions are derived)
+    fn max_mass_error(&self) -> Option<f64> {
+        None
+    }
+
+    /// Registry of all policies on this model: the policies implementing `algorithm` (none if it does not exist)
+    fn policy(algorithm: &Algorithm, options: &PolicyOptions) -> Vec<Box<dyn Policy<Self>>>;
+
+    /// All policies on this model in the order of `Algorithm::ALGORITHMS`
+    fn policies(options: &PolicyOptions) -> Vec<Box<dyn Policy<Self>>> {
+        Algorithm::ALGORITHMS
+            .iter()
+            .flat_map(|algorithm| Self::policy(algorithm, options))
+            .collect()
+    }
+
+    /// Policies of the algorithms in `algorithms` (`ALL` selects all of them) in the order of `Algorithm::ALGORITHMS`,
+    /// where OPT is selected regardless of `algorithms` unless `not_opt` is set. Also returns every explicitly requested
+    /// algorithm without a policy on this model, e.g. EXT on ProbeMax or BAMP without rounds.
+    fn select_policies(
+        algorithms: &[Algorithm],
+        not_opt: bool,
+        options: &PolicyOptions,
+    ) -> (Vec<Box<dyn Policy<Self>>>, Vec<Algorithm>) {
+        let unavailable = algorithms
+            .iter()
+            .filter(|a| **a != Algorithm::ALL && Self::policy(a, options).is_empty())
+            .copied()
+            .collect();
+
+        let selected = Self::policies(options)
+            .into_iter()
+            .filter(|policy| match policy.algorithm() {
+                Algorithm::OPT => !not_opt,
+                algorithm => algorithms
+                    .iter()
+                    .any(|a| *a == algorithm || *a == Algorithm::ALL),
+            })
+            .collect();
+
+        (selected, unavailable)
+    }
+}
+
+/// Probing policy: probes up to `k` regulators and selects `l` of them
+pub trait Policy<M: ProbingModel> {
+    /// Algorithm implemented by this policy
+    fn algorithm(&self) -> Algorithm;
+
+    /// Does the policy probe all regulators, i.e. is it only run for `k = na`
+    fn is_offline(&self) -> bool {
+        false
+    }
+
+    /// Number of rounds in which the policy probes (only for batched policies)
+    fn rounds(&self) -> Option<usize> {
+        None
+    }
+
+    /// Precompute everything that does not depend on the realizations for the pair (k, l)
+    fn prepare(&self, _model: &mut M, _k: usize, _l: usize) {}
+
+    /// Get the probed regulators and the time needed (including precomputation)
+    fn probe(&self, instance: &M::Instance<'_>, k: usize, l: usize) -> (Vec<usize>, f64);
+
+    /// Get the value of the policy and the time needed (including precomputation)
+    fn run(&self, instance: &M::Instance<'_>, k: usize, l: usize) -> (f64, f64) {
+        let (probed, time) = self.probe(instance, k, l);
+
+        let timer = Instant::now();
+        let value = instance.eval_policy(&probed, l);
+
+        (value, time + timer.elapsed().as_secs_f64())
+    }
+
+    /// Get the regulators probed within a total probing cost of `budget` and the time needed,
+    /// or `None` if the policy does not support budgets
+    fn probe_budget(
+        &self,
+        _instance: &M::Instance<'_>,
+        _budget: f64,
+        _l: usize,
+    ) -> Option<(Vec<usize>, f64)> {
+        None
+    }
+
+    /// Get the probed regulators, the value of the policy within `budget` and the time needed,
+    /// or `None` if the policy does not support budgets
+    fn run_budget(
+        &self,
+        instance: &M::Instance<'_>,
+        budget: f64,
+        l: usize,
+    ) -> Option<(Vec<usize>, f64, f64)> {
+        let (probed, time) = self.probe_budget(instance, budget, l)?;
+
+        let timer = Instant::now();
+        let value = instance.eval_policy(&probed, l);
+
+        Some((probed, value, time + timer.elapsed().as_secs_f64()))
+    }
+
+    /// Get the edges `(a, b)` probed within a budget of `budget` single edges, the `l` regulators selected based on
+    /// the partially revealed regulators and the time needed, or `None` if the policy does not probe single edges
+    fn probe_edges(
+        &self,
+        _instance: &M::Instance<'_>,
+        _budget: usize,
+        _l: usize,
+    ) -> Option<(Vec<Edge>, Vec<usize>, f64)> {
+        None
+    }
+
+    /// Get the probed edges, the value of the selected regulators and the time needed,
+    /// or `None` if the policy does not probe single edges
+    fn run_edges(
+        &self,
+        instance: &M::Instance<'_>,
+        budget: usize,
+        l: usize,
+    ) -> Option<(Vec<Edge>, f64, f64)> {
+        let (probed, selected, time) = self.probe_edges(instance, budget, l)?;
+
+        let timer = Instant::now();
+        let value = instance.eval_policy(&selected, l);
+
+        Some((probed, value, time + timer.elapsed().as_secs_f64()))
+    }
+}
+
+/// Optimal-Offline-Algorithm (probes every regulator)
+pub struct OptimalPolicy {
+    /// Use the IP-Formulation (for Coverage only)
+    pub ip: bool,
+}
+
+/// Adaptive-Myopic-Policy
+pub struct AdaptivePolicy;
+
+/// Non-Adaptive-Myopic-Policy
+pub struct NonAdaptivePolicy;
+
+/// Non-Adaptive-Myopic-Policy computed for `l = k` (for Coverage only)
+pub struct ExtendedNonAdaptivePolicy;
+
+/// Adaptive-Myopic-Policy probing in batches over a fixed number of rounds
+pub struct BatchedPolicy {
+    /// Number of rounds
+    pub rounds: usize,
+}
+
+impl BatchedPolicy {
+    /// One batched policy for every number of rounds
+    fn all<M: ProbingModel>(options: &PolicyOptions) -> Vec<Box<dyn Policy<M>>>
+    where
+        Self: Policy<M>,
+    {
+        options
+            .rounds
+            .iter()
+            .map(|rounds| Box::new(BatchedPolicy { rounds: *rounds }) as Box<dyn Policy<M>>)
+            .collect()
+    }
+}
+
+impl ProbingInstance for ProbeMaxInstance<'_> {
+    fn eval_policy(&self, probed: &[usize], l: usize) -> f64 {
+        ProbeMaxInstance::eval_policy(self, probed, l)
+    }
+}
+
+impl ProbingModel for ProbeMax {
+    type Instance<'a> = ProbeMaxInstance<'a>;
+
+    fn get_bpr(&self) -> &BipartiteRegulatorProbing {
+        self.get_model()
+    }
+
+    fn create_instance(&self, instance_index: usize) -> Self::Instance<'_> {
+        ProbeMaxInstance::new(self, instance_index)
+    }
+
+    fn max_mass_error(&self) -> Option<f64> {
+        self.get_mass_errors().into_iter().reduce(f64::max)
+    }
+
+    fn policy(algorithm: &Algorithm, options: &PolicyOptions) -> Vec<Box<dyn Policy<Self>>> {
+        match algorithm {
+            Algorithm::OPT => vec![Box::new(OptimalPolicy { ip: false })],
+            Algorithm::AMP => vec![Box::new(AdaptivePolicy)],
+            Algorithm::NAMP => vec![Box::new(NonAdaptivePolicy)],
+            Algorithm::BAMP => BatchedPolicy::all(options),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Policy<ProbeMax> for OptimalPolicy {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::OPT
+    }
+
+    fn is_offline(&self) -> bool {
+        true
+    }
+
+    fn probe(&self, instance: &ProbeMaxInstance<'_>, _k: usize, _l: usize) -> (Vec<usize>, f64) {
+        (
+            (0..instance.get_probemax().get_n()).collect(),
+            instance.get_optimal_time(),
+        )
+    }
+
+    fn run(&self, instance: &ProbeMaxInstance<'_>, _k: usize, l: usize) -> (f64, f64) {
+        (instance.get_optimal_value(l), instance.get_optimal_time())
+    }
+}
+
+impl Policy<ProbeMax> for AdaptivePolicy {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::AMP
+    }
+
+    fn probe(&self, instance: &ProbeMaxInstance<'_>, k: usize, l: usize) -> (Vec<usize>, f64) {
+        let timer = Instant::now();
+        let probed = instance.adaptive_policy_boxes(k, l);
+
+        (
+            probed,
+            instance.get_probemax().get_policy_time() + timer.elapsed().as_secs_f64(),
+        )
+    }
+
+    fn probe_budget(
+        &self,
+        instance: &ProbeMaxInstance<'_>,
+        budget: f64,
+        l: usize,
+    ) -> Option<(Vec<usize>, f64)> {
+        let timer = Instant::now();
+        let probed = instance.adaptive_policy_boxes_budget(budget, l);
+
+        Some((probed, timer.elapsed().as_secs_f64()))
+    }
+}
+
+impl Policy<ProbeMax> for NonAdaptivePolicy {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::NAMP
+    }
+
+    fn probe(&self, instance: &ProbeMaxInstance<'_>, k: usize, _l: usize) -> (Vec<usize>, f64) {
+        let pm = instance.get_probemax();
+        (pm.get_policy()[..k].to_vec(), pm.get_policy_time())
+    }
+
+    fn probe_budget(
+        &self,
+        instance: &ProbeMaxInstance<'_>,
+        budget: f64,
+        _l: usize,
+    ) -> Option<(Vec<usize>, f64)> {
+        let timer = Instant::now();
+        let probed = instance.get_probemax().compute_namp_budget_policy(budget);
+
+        Some((probed, timer.elapsed().as_secs_f64()))
+    }
+}
+
+impl Policy<ProbeMax> for BatchedPolicy {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::BAMP
+    }
+
+    fn rounds(&self) -> Option<usize> {
+        Some(self.rounds)
+    }
+
+    fn probe(&self, instance: &ProbeMaxInstance<'_>, k: usize, l: usize) -> (Vec<usize>, f64) {
+        let timer = Instant::now();
+        let probed = instance.batched_policy_boxes(k, l, self.rounds);
+
+        (probed, timer.elapsed().as_secs_f64())
+    }
+}
+
+impl ProbingInstance for Instance<'_> {
+    fn eval_policy(&self, probed: &[usize], l: usize) -> f64 {
+        Instance::eval_policy(self, probed, l)
+    }
+}
+
+impl ProbingModel for BipartiteRegulatorProbing {
+    type Instance<'a> = Instance<'a>;
+
+    fn get_bpr(&self) -> &BipartiteRegulatorProbing {
+        self
+    }
+
+    fn create_instance(&self, instance_index: usize) -> Self::Instance<'_> {
+        Instance::new(self, instance_index)
+    }
+
+    fn policy(algorithm: &Algorithm, options: &PolicyOptions) -> Vec<Box<dyn Policy<Self>>> {
+        match algorithm {
+            Algorithm::OPT => vec![Box::new(OptimalPolicy { ip: options.ip_opt })],
+            Algorithm::AMP => vec![Box::new(AdaptivePolicy)],
+            Algorithm::NAMP => vec![Box::new(NonAdaptivePolicy)],
+            Algorithm::EXT => vec![Box::new(ExtendedNonAdaptivePolicy)],
+            Algorithm::BAMP => BatchedPolicy::all(options),
+            Algorithm::ALL => Vec::new(),
+        }
+    }
+}
+
+impl Policy<BipartiteRegulatorProbing> for OptimalPolicy {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::OPT
+    }
+
+    fn is_offline(&self) -> bool {
+        true
+    }
+
+    fn probe(&self, instance: &Instance<'_>, _k: usize, _l: usize) -> (Vec<usize>, f64) {
+        (
+            (0..instance.get_model().get_na()).collect(),
+            instance.get_opt_cov_time(),
+        )
+    }
+
+    fn run(&self, instance: &Instance<'_>, _k: usize, l: usize) -> (f64, f64) {
+        if !self.ip {
+            return (instance.get_opt_cov_value(l), instance.get_opt_cov_time());
+        }
+
+        let timer = Instant::now();
+        let bpr = instance.get_model();
+        let value = solve_cov_instance(
+            bpr.get_na(),
+            bpr.get_nb(),
+            l,
+            &bpr.positions,
+            &instance.realizations,
+            bpr.get_position_weights(),
+            bpr.group_constraint.as_ref(),
+        );
+
+        (value, timer.elapsed().as_secs_f64())
+    }
+}
+
+impl Policy<BipartiteRegulatorProbing> for AdaptivePolicy {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::AMP
+    }
+
+    fn probe(&self, instance: &Instance<'_>, k: usize, l: usize) -> (Vec<usize>, f64) {
+        let timer = Instant::now();
+        let probed = instance.adaptive_policy_regulators(k, l);
+
+        (probed, timer.elapsed().as_secs_f64())
+    }
+
+    fn probe_budget(
+        &self,
+        instance: &Instance<'_>,
+        budget: f64,
+        l: usize,
+    ) -> Option<(Vec<usize>, f64)> {
+        let timer = Instant::now();
+        let probed = instance.adaptive_policy_regulators_budget(budget, l);
+
+        Some((probed, timer.elapsed().as_secs_f64()))
+    }
+
+    fn probe_edges(
+        &self,
+        instance: &Instance<'_>,
+        budget: usize,
+        l: usize,
+    ) -> Option<(Vec<Edge>, Vec<usize>, f64)> {
+        let timer = Instant::now();
+        let probed = instance.adaptive_policy_edges(budget, l);
+        let selected = instance.select_by_edges(&probed, l);
+
+        Some((probed, selected, timer.elapsed().as_secs_f64()))
+    }
+}
+
+impl Policy<BipartiteRegulatorProbing> for NonAdaptivePolicy {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::NAMP
+    }
+
+    fn prepare(&self, model: &mut BipartiteRegulatorProbing, k: usize, l: usize) {
+        model.compute_namp_cov_policy(k, l);
+    }
+
+    fn probe(&self, instance: &Instance<'_>, k: usize, l: usize) -> (Vec<usize>, f64) {
+        let bpr = instance.get_model();
+        (
+            bpr.get_policy(k, l).unwrap().clone(),
+            bpr.get_policy_time(k, l).unwrap(),
+        )
+    }
+
+    fn probe_budget(
+        &self,
+        instance: &Instance<'_>,
+        budget: f64,
+        l: usize,
+    ) -> Option<(Vec<usize>, f64)> {
+        let timer = Instant::now();
+        let probed = instance
+            .get_model()
+            .compute_namp_cov_budget_policy(budget, l);
+
+        Some((probed, timer.elapsed().as_secs_f64()))
+    }
+
+    fn probe_edges(
+        &self,
+        instance: &Instance<'_>,
+        budget: usize,
+        l: usize,
+    ) -> Option<(Vec<Edge>, Vec<usize>, f64)> {
+        let timer = Instant::now();
+        let probed = instance.get_model().compute_namp_edge_policy(budget, l);
+        let selected = instance.select_by_edges(&probed, l);
+
+        Some((probed, selected, timer.elapsed().as_secs_f64()))
+    }
+}
+
+impl Policy<BipartiteRegulatorProbing> for ExtendedNonAdaptivePolicy {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::EXT
+    }
+
+    fn prepare(&self, model: &mut BipartiteRegulatorProbing, k: usize, _l: usize) {
+        model.compute_namp_ext_cov_policy(k);
+    }
+
+    fn probe(&self, instance: &Instance<'_>, k: usize, _l: usize) -> (Vec<usize>, f64) {
+        let bpr = instance.get_model();
+        (
+            bpr.get_policy(k, k).unwrap().clone(),
+            bpr.get_policy_time(k, k).unwrap(),
+        )
+    }
+}
+
+impl Policy<BipartiteRegulatorProbing> for BatchedPolicy {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::BAMP
+    }
+
+    fn rounds(&self) -> Option<usize> {
+        Some(self.rounds)
+    }
+
+    fn probe(&self, instance: &Instance<'_>, k: usize, l: usize) -> (Vec<usize>, f64) {
+        let timer = Instant::now();
+        let probed = instance.batched_policy_regulators(k, l, self.rounds);
+
+        (probed, timer.elapsed().as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn select<M: ProbingModel>(
+        algorithms: &str,
+        not_opt: bool,
+        rounds: &[usize],
+    ) -> (Vec<(Algorithm, Option<usize>)>, Vec<Algorithm>) {
+        let algorithms: Vec<Algorithm> =
+            algorithms.split(',').map(|a| a.parse().unwrap()).collect();
+        let options = PolicyOptions {
+            ip_opt: false,
+            rounds: rounds.to_vec(),
+        };
+        let (selected, unavailable) = M::select_policies(&algorithms, not_opt, &options);
+        (
+            selected
+                .iter()
+                .map(|policy| (policy.algorithm(), policy.rounds()))
+                .collect(),
+            unavailable,
+        )
+    }
+
+    #[test]
+    fn algorithm_selection() {
+        use Algorithm::*;
+        type Bpr = BipartiteRegulatorProbing;
+
+        // A subset is run in the order of `Algorithm::ALGORITHMS` together with OPT unless it is disabled
+        let (selected, unavailable) = select::<Bpr>("EXT,AMP", false, &[]);
+        assert_eq!(selected, vec![(OPT, None), (AMP, None), (EXT, None)]);
+        assert!(unavailable.is_empty());
+        let (selected, _) = select::<Bpr>("AMP,EXT", true, &[]);
+        assert_eq!(selected, vec![(AMP, None), (EXT, None)]);
+        let (selected, _) = select::<Bpr>("OPT", true, &[]);
+        assert!(selected.is_empty());
+
+        // EXT is only available for Coverage
+        let (selected, unavailable) = select::<ProbeMax>("AMP,EXT", false, &[]);
+        assert_eq!(selected, vec![(OPT, None), (AMP, None)]);
+        assert_eq!(unavailable, vec![EXT]);
+
+        // BAMP is only run with rounds, once for every number of rounds
+        let (selected, unavailable) = select::<Bpr>("BAMP", true, &[]);
+        assert!(selected.is_empty());
+        assert_eq!(unavailable, vec![BAMP]);
+        let (selected, unavailable) = select::<ProbeMax>("BAMP", true, &[2, 4]);
+        assert_eq!(selected, vec![(BAMP, Some(2)), (BAMP, Some(4))]);
+        assert!(unavailable.is_empty());
+
+        // ALL selects every available policy without reporting the missing ones
+        let (selected, unavailable) = select::<ProbeMax>("ALL", false, &[]);
+        assert_eq!(selected, vec![(OPT, None), (AMP, None), (NAMP, None)]);
+        assert!(unavailable.is_empty());
+        let (selected, _) = select::<Bpr>("ALL", false, &[3]);
+        assert_eq!(
+            selected,
+            vec![
+                (OPT, None),
+                (AMP, None),
+                (NAMP, None),
+                (EXT, None),
+                (BAMP, Some(3))
+            ]
+        );
+
+        assert!("AMP,FOO"
+            .split(',')
+            .any(|a| a.parse::<Algorithm>().is_err()));
+    }
+}
@@ -0,0 +1,494 @@
+//! # Storage
+//!
+//! Saving and loading of complete BPR-models: their edge distributions, cached non-adaptive policies
+//! and stored realizations. Models are stored either as JSON or in a compact little-endian binary format.
+//!
+//! The binary format is laid out as follows, where every integer is a `u64` unless stated otherwise:
+//! - the magic bytes `BPR\0` and the version as `u32`
+//! - `na`, `nb`, `seed`, `correlation` and `noise` (both as `f64`)
+//! - the `nb` position weights and the `na` regulator costs as `f64`
+//! - the number of groups `g` of the group constraint (`0` if there is none), the `g` capacities and, if `g > 0`,
+//!   the group of every regulator with `u64::MAX` for regulators without a group
+//! - for every regulator: its degree `d` and the `d` sorted positions of its edges
+//! - for every edge (regulator by regulator): a `u8` which is `1` if the support is stored explicitly,
+//!   the support size `n`, the `n` support values as `f64` (only if stored explicitly) and the `n` probabilities
+//! - the number of cached policies, each as `k`, `l`, the number of regulators, the regulators and the time as `f64`
+//! - the number of stored instances, each as one realization as `f64` for every edge (regulator by regulator)
+
+use std::{
+    convert::TryFrom,
+    fs::File,
+    io::{BufReader, BufWriter, Error, ErrorKind, Read, Result, Write},
+    path::Path,
+};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    distributions::WeightedDistribution,
+    model::{BipartiteRegulatorProbing, GroupConstraint},
+    StorageFormat,
+};
+
+/// First bytes of every binary file
+pub const MAGIC: &[u8; 4] = b"BPR\0";
+
+/// Version of the on-disk format (JSON and binary)
+pub const VERSION: u32 = 1;
+
+/// JSON representation of a stored model
+#[derive(Serialize, Deserialize)]
+struct StoredModel<M> {
+    version: u32,
+    model: M,
+}
+
+/// Save a BPR-model to a file
+pub fn save_model(
+    bpr: &BipartiteRegulatorProbing,
+    path: &Path,
+    format: &StorageFormat,
+) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    match format {
+        StorageFormat::JSON => serde_json::to_writer(
+            &mut writer,
+            &StoredModel {
+                version: VERSION,
+                model: bpr,
+            },
+        )?,
+        StorageFormat::BINARY => write_binary(bpr, &mut writer)?,
+    };
+
+    writer.flush()
+}
+
+/// Load a BPR-model from a file. The format is detected by the magic bytes of the binary format.
+pub fn load_model(path: &Path) -> Result<BipartiteRegulatorProbing> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0u8; 4];
+    let is_binary = match reader.read_exact(&mut magic) {
+        Ok(()) => &magic == MAGIC,
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => false,
+        Err(e) => return Err(e),
+    };
+
+    if is_binary {
+        return read_binary_body(&mut reader);
+    }
+
+    let stored: StoredModel<BipartiteRegulatorProbing> =
+        serde_json::from_reader(magic.chain(reader))?;
+    check_version(stored.version)?;
+    check_model(stored.model)
+}
+
+/// Write a BPR-model in the binary format
+pub fn write_binary<W: Write>(bpr: &BipartiteRegulatorProbing, writer: &mut W) -> Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+
+    write_usize(writer, bpr.na)?;
+    write_usize(writer, bpr.nb)?;
+    write_u64(writer, bpr.seed)?;
+    write_f64(writer, bpr.correlation)?;
+    write_f64(writer, bpr.noise)?;
+    for w in &bpr.position_weights {
+        write_f64(writer, *w)?;
+    }
+    for c in &bpr.regulator_costs {
+        write_f64(writer, *c)?;
+    }
+    match &bpr.group_constraint {
+        Some(constraint) => {
+            write_usize(writer, constraint.capacities.len())?;
+            for c in &constraint.capacities {
+                write_usize(writer, *c)?;
+            }
+            for g in &constraint.groups {
+                write_u64(writer, g.map_or(u64::MAX, |g| g as u64))?;
+            }
+        }
+        None => write_usize(writer, 0)?,
+    }
+
+    for positions in &bpr.positions {
+        write_usize(writer, positions.len())?;
+        for b in positions {
+            write_usize(writer, *b)?;
+        }
+    }
+
+    for edge in bpr.edges.iter().flatten() {
+        writer.write_all(&[!edge.has_integer_support() as u8])?;
+        write_usize(writer, edge.size())?;
+        if !edge.has_integer_support() {
+            for v in edge.get_values() {
+                write_f64(writer, v)?;
+            }
+        }
+        for p in edge.get_probs() {
+            write_f64(writer, p)?;
+        }
+    }
+
+    write_usize(writer, bpr.non_adaptive_cov_policies.len())?;
+    for (k, l, policy, time) in &bpr.non_adaptive_cov_policies {
+        write_usize(writer, *k)?;
+        write_usize(writer, *l)?;
+        write_usize(writer, policy.len())?;
+        for a in policy {
+            write_usize(writer, *a)?;
+        }
+        write_f64(writer, *time)?;
+    }
+
+    write_usize(writer, bpr.stored_realizations.len())?;
+    for x in bpr.stored_realizations.iter().flatten().flatten() {
+        write_f64(writer, *x)?;
+    }
+
+    Ok(())
+}
+
+/// Read a BPR-model in the binary format
+pub fn read_binary<R: Read>(reader: &mut R) -> Result<BipartiteRegulatorProbing> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("Not a binary BPR-model!".to_string()));
+    }
+
+    read_binary_body(reader)
+}
+
+/// Read a BPR-model in the binary format after its magic bytes
+fn read_binary_body<R: Read>(reader: &mut R) -> Result<BipartiteRegulatorProbing> {
+    let mut version = [0u8; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    check_version(version)?;
+
+    let na = read_usize(reader)?;
+    let nb = read_usize(reader)?;
+    let seed = read_u64(reader)?;
+    let correlation = read_f64(reader)?;
+    let noise = read_f64(reader)?;
+    let position_weights = read_f64s(reader, nb)?;
+    let regulator_costs = read_f64s(reader, na)?;
+    let num_groups = read_usize(reader)?;
+    let group_constraint = if num_groups > 0 {
+        let capacities = (0..num_groups)
+            .map(|_| read_usize(reader))
+            .collect::<Result<Vec<usize>>>()?;
+        let groups = (0..na)
+            .map(|_| {
+                read_u64(reader).and_then(|g| match g {
+                    u64::MAX => Ok(None),
+                    g => usize::try_from(g)
+                        .map(Some)
+                        .map_err(|_| invalid_data(format!("Invalid group {}!", g))),
+                })
+            })
+            .collect::<Result<Vec<Option<usize>>>>()?;
+        Some(GroupConstraint { groups, capacities })
+    } else {
+        None
+    };
+
+    let positions = (0..na)
+        .map(|_| {
+            let degree = read_usize(reader)?;
+            (0..degree)
+                .map(|_| read_usize(reader))
+                .collect::<Result<Vec<usize>>>()
+        })
+        .collect::<Result<Vec<Vec<usize>>>>()?;
+
+    let mut edges: Vec<Vec<WeightedDistribution>> = Vec::new();
+    for a in 0..na {
+        let mut regulator = Vec::new();
+        for &b in &positions[a] {
+            let mut explicit = [0u8; 1];
+            reader.read_exact(&mut explicit)?;
+            let n = read_usize(reader)?;
+
+            let values = if explicit[0] == 1 {
+                Some(read_f64s(reader, n)?)
+            } else {
+                None
+            };
+            let probs = read_f64s(reader, n)?;
+
+            let edge = match values {
+                Some(values) => WeightedDistribution::try_with_support(&values, &probs),
+                None => WeightedDistribution::try_new(&probs),
+            }
+            .map_err(|e| invalid_data(format!("Edge ({}, {}): {}", a, b, e)))?;
+            regulator.push(edge);
+        }
+        edges.push(regulator);
+    }
+
+    let mut bpr = BipartiteRegulatorProbing::from_edge_lists(na, nb, edges, positions, seed);
+    bpr.correlation = correlation;
+    bpr.noise = noise;
+    bpr.position_weights = position_weights;
+    bpr.regulator_costs = regulator_costs;
+    bpr.group_constraint = group_constraint;
+
+    for _ in 0..read_usize(reader)? {
+        let k = read_usize(reader)?;
+        let l = read_usize(reader)?;
+        let len = read_usize(reader)?;
+        let policy = (0..len)
+            .map(|_| read_usize(reader))
+            .collect::<Result<Vec<usize>>>()?;
+        let time = read_f64(reader)?;
+        bpr.non_adaptive_cov_policies.push((k, l, policy, time));
+    }
+
+    for _ in 0..read_usize(reader)? {
+        let realizations = (0..na)
+            .map(|a| read_f64s(reader, bpr.positions[a].len()))
+            .collect::<Result<Vec<Vec<f64>>>>()?;
+        bpr.stored_realizations.push(realizations);
+    }
+
+    check_model(bpr)
+}
+
+/// Check that a loaded model is consistent
+fn check_model(bpr: BipartiteRegulatorProbing) -> Result<BipartiteRegulatorProbing> {
+    if bpr.positions.len() != bpr.na
+        || bpr
+            .positions
+            .iter()
+            .any(|p| p.windows(2).any(|w| w[0] >= w[1]) || p.last().is_some_and(|b| *b >= bpr.nb))
+    {
+        return Err(invalid_data(format!(
+            "Expected sorted positions below {} for each of the {} regulators!",
+            bpr.nb, bpr.na
+        )));
+    }
+
+    if bpr.edges.len() != bpr.na
+        || bpr
+            .edges
+            .iter()
+            .zip(&bpr.positions)
+            .any(|(r, p)| r.len() != p.len())
+    {
+        return Err(invalid_data(
+            "Expected one edge per position of every regulator!".to_string(),
+        ));
+    }
+
+    if !(0.0..=1.0).contains(&bpr.correlation) {
+        return Err(invalid_data(format!(
+            "Invalid correlation {}!",
+            bpr.correlation
+        )));
+    }
+
+    if !bpr.noise.is_finite() || bpr.noise < 0.0 {
+        return Err(invalid_data(format!("Invalid noise {}!", bpr.noise)));
+    }
+
+    if bpr.position_weights.len() != bpr.nb
+        || bpr
+            .position_weights
+            .iter()
+            .any(|w| !w.is_finite() || *w < 0.0)
+    {
+        return Err(invalid_data(format!(
+            "Expected {} non-negative position weights!",
+            bpr.nb
+        )));
+    }
+
+    if bpr.regulator_costs.len() != bpr.na
+        || bpr
+            .regulator_costs
+            .iter()
+            .any(|c| !c.is_finite() || *c <= 0.0)
+    {
+        return Err(invalid_data(format!(
+            "Expected {} positive regulator costs!",
+            bpr.na
+        )));
+    }
+
+    if let Some(constraint) = &bpr.group_constraint {
+        if constraint.groups.len() != bpr.na
+            || constraint
+                .groups
+                .iter()
+                .flatten()
+                .any(|g| *g >= constraint.capacities.len())
+        {
+            return Err(invalid_data(format!(
+                "Expected a group out of {} for each of the {} regulators!",
+                constraint.capacities.len(),
+                bpr.na
+            )));
+        }
+    }
+
+    if let Some((k, l, _, _)) = bpr
+        .non_adaptive_cov_policies
+        .iter()
+        .find(|(k, l, policy, _)| {
+            policy.len() != *k || l > k || policy.iter().any(|a| *a >= bpr.na)
+        })
+    {
+        return Err(invalid_data(format!(
+            "Invalid policy for k = {}, l = {}!",
+            k, l
+        )));
+    }
+
+    if bpr.stored_realizations.iter().any(|r| {
+        r.len() != bpr.na
+            || r.iter()
+                .zip(&bpr.positions)
+                .any(|(row, p)| row.len() != p.len())
+    }) {
+        return Err(invalid_data(
+            "Expected one realization per edge and instance!".to_string(),
+        ));
+    }
+
+    // `vs` is derived from the edges and does not need to be trusted
+    Ok(BipartiteRegulatorProbing {
+        vs: bpr
+            .edges
+            .iter()
+            .flatten()
+            .map(|d| d.size())
+            .max()
+            .unwrap_or(0),
+        ..bpr
+    })
+}
+
+fn check_version(version: u32) -> Result<()> {
+    if version != VERSION {
+        return Err(invalid_data(format!(
+            "Unsupported format version {} (expected {})!",
+            version, VERSION
+        )));
+    }
+    Ok(())
+}
+
+fn invalid_data(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn write_u64<W: Write>(writer: &mut W, x: u64) -> Result<()> {
+    writer.write_all(&x.to_le_bytes())
+}
+
+fn write_usize<W: Write>(writer: &mut W, x: usize) -> Result<()> {
+    write_u64(writer, x as u64)
+}
+
+fn write_f64<W: Write>(writer: &mut W, x: f64) -> Result<()> {
+    writer.write_all(&x.to_le_bytes())
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_usize<R: Read>(reader: &mut R) -> Result<usize> {
+    let x = read_u64(reader)?;
+    usize::try_from(x).map_err(|_| invalid_data(format!("Value {} is too large!", x)))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> Result<f64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(f64::from_le_bytes(bytes))
+}
+
+/// Read `n` values without trusting `n` for the allocation
+fn read_f64s<R: Read>(reader: &mut R, n: usize) -> Result<Vec<f64>> {
+    (0..n).map(|_| read_f64(reader)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sparse model using every stored feature
+    fn full_model() -> BipartiteRegulatorProbing {
+        let edges = vec![
+            vec![
+                (0, WeightedDistribution::new(&[0.25, 0.5, 0.25])),
+                (
+                    2,
+                    WeightedDistribution::with_support(&[-1.5, 0.5, 4.0], &[0.2, 0.3, 0.5]),
+                ),
+            ],
+            vec![],
+            vec![
+                (1, WeightedDistribution::with_support(&[0.1], &[1.0])),
+                (2, WeightedDistribution::new(&[0.1, 0.2, 0.3, 0.4])),
+                (3, WeightedDistribution::new(&[0.9, 0.1])),
+            ],
+        ];
+
+        let mut bpr = BipartiteRegulatorProbing::new_sparse(3, 4, edges, 42);
+        bpr.set_correlation(0.3);
+        bpr.set_noise(0.75);
+        bpr.set_position_weights(vec![1.0, 0.5, 2.0, 0.0]);
+        bpr.set_regulator_costs(vec![1.0, 2.5, 1.5]);
+        bpr.set_group_constraint(GroupConstraint::new(vec![Some(0), None, Some(0)], vec![1]));
+        bpr.add_policy(2, 1, vec![2, 0], 0.125);
+        bpr.store_realizations(3);
+        bpr
+    }
+
+    fn round_trip(format: StorageFormat) {
+        let bpr = full_model();
+        let path = std::env::temp_dir().join(format!(
+            "bpr_storage_{:?}_{}.model",
+            format,
+            std::process::id()
+        ));
+
+        save_model(&bpr, &path, &format).unwrap();
+        let loaded = load_model(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            serde_json::to_string(&loaded.unwrap()).unwrap(),
+            serde_json::to_string(&bpr).unwrap()
+        );
+    }
+
+    #[test]
+    fn json_round_trip() {
+        round_trip(StorageFormat::JSON);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        round_trip(StorageFormat::BINARY);
+    }
+
+    #[test]
+    fn reject_other_versions() {
+        let mut bytes = Vec::new();
+        write_binary(&full_model(), &mut bytes).unwrap();
+        bytes[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(read_binary(&mut bytes.as_slice()).is_err());
+    }
+}
@@ -1,17 +1,18 @@
 use std::{
+    collections::{BTreeMap, HashMap},
     fs::File,
     io::{BufRead, BufReader, Error},
     path::PathBuf,
-    time::Instant,
 };
 
 use bpr::{
-    distributions::WeightedDistribution,
-    model::{BipartiteRegulatorProbing, NUM_TOP_TUPLES},
+    distributions::{DistributionError, WeightedDistribution},
+    model::{BipartiteRegulatorProbing, GroupConstraint, NUM_TOP_TUPLES},
+    policies::{PolicyOptions, ProbingModel},
+    Binning, DistributionFamily, MergeRule,
 };
 use serde::Serialize;
 use serde_derive::Serialize;
-use statrs::distribution::{Binomial, Discrete};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -30,12 +31,98 @@ struct Parameters {
 
     #[structopt(long)]
     noopt: bool,
+
+    /// Merge the motif variants of a TF (columns SYMBOL(MOTIF) with the same symbol) into one TF: MAX, SUM or MIN
+    #[structopt(long)]
+    merge_motifs: Option<MergeRule>,
+
+    /// Probe TFs within this total probing cost instead of k TFs (for AMP and NAMP)
+    #[structopt(long)]
+    budget: Option<f64>,
+
+    /// File with lines `TF<TAB>group[<TAB>capacity]`: at most capacity TFs of a group are selected.
+    /// TF is a TF name, a motif variant like `RORA(MA0071.1)` or a gene symbol (all its variants)
+    #[structopt(long, parse(from_os_str))]
+    groups: Option<PathBuf>,
+
+    /// Capacity of all groups without an explicit capacity
+    #[structopt(long, default_value = "1")]
+    group_capacity: usize,
+
+    /// Seed of the experiment (chosen randomly if not given)
+    #[structopt(long)]
+    seed: Option<u64>,
+
+    /// Family of the Edge-Distributions: BINOMIAL, BETABINOMIAL, POISSON, GEOMETRIC, NEGBINOMIAL or BERNOULLI
+    #[structopt(long, default_value = "BINOMIAL")]
+    distribution: DistributionFamily,
+
+    /// Overdispersion of BETABINOMIAL (intra-class correlation) and NEGBINOMIAL (inverse size)
+    #[structopt(long, default_value = "0.1")]
+    dispersion: f64,
+
+    /// Correlation of the edges of a TF in [0, 1] (Gaussian copula with a latent TF strength)
+    #[structopt(long, default_value = "0.0")]
+    correlation: f64,
+
+    /// Standard deviation of the Gaussian noise of every probe, e.g. the variability between assay replicates
+    #[structopt(long, default_value = "0.0")]
+    noise: f64,
+
+    /// Map every edge onto at most this many support values
+    #[structopt(long)]
+    bins: Option<usize>,
+
+    /// Binning used for --bins: UNIFORM or QUANTILE
+    #[structopt(long, default_value = "UNIFORM")]
+    binning: Binning,
 }
 
 fn main() -> std::io::Result<()> {
     let params = Parameters::from_args();
+    let seed = params.seed.unwrap_or_else(rand::random);
+
+    let (mut tf_names, gen_names, mut instance) =
+        parse_file(&params.file, seed, &params.distribution, params.dispersion)?;
+
+    if let Some(rule) = &params.merge_motifs {
+        let columns = tf_names.len();
+        (tf_names, instance) = merge_motifs(&tf_names, &instance, rule);
+        let mass_error = instance
+            .edges
+            .iter()
+            .flatten()
+            .map(|edge| edge.mass_error())
+            .fold(0.0, f64::max);
+        eprintln!(
+            "Merged {} motif columns into {} TFs (largest mass error of a merged edge: {})",
+            columns,
+            tf_names.len(),
+            mass_error
+        );
+    }
 
-    let (tf_names, gen_names, instance) = parse_file(&params.file, params.iterations)?;
+    if !(0.0..=1.0).contains(&params.correlation) {
+        return Err(Error::other("Correlation must be in [0, 1]!"));
+    }
+    instance.set_correlation(params.correlation);
+
+    if !params.noise.is_finite() || params.noise < 0.0 {
+        return Err(Error::other("Noise must be non-negative!"));
+    }
+    instance.set_noise(params.noise);
+
+    if let Some(path) = &params.groups {
+        instance.set_group_constraint(parse_groups(path, &tf_names, params.group_capacity)?);
+    }
+
+    if let Some(bins) = params.bins {
+        let bound = instance.rebin(bins, &params.binning);
+        eprintln!(
+            "Rebinned edges to at most {} values, expected values change by at most {}",
+            bins, bound
+        );
+    }
 
     match params.l {
         1 => eval_cov::<1>(
@@ -45,6 +132,7 @@ fn main() -> std::io::Result<()> {
             params.k,
             params.iterations,
             params.noopt,
+            params.budget,
         ),
         2 => eval_cov::<2>(
             tf_names,
@@ -53,6 +141,7 @@ fn main() -> std::io::Result<()> {
             params.k,
             params.iterations,
             params.noopt,
+            params.budget,
         ),
         3 => eval_cov::<3>(
             tf_names,
@@ -61,6 +150,7 @@ fn main() -> std::io::Result<()> {
             params.k,
             params.iterations,
             params.noopt,
+            params.budget,
         ),
         4 => eval_cov::<4>(
             tf_names,
@@ -69,6 +159,7 @@ fn main() -> std::io::Result<()> {
             params.k,
             params.iterations,
             params.noopt,
+            params.budget,
         ),
         5 => eval_cov::<5>(
             tf_names,
@@ -77,6 +168,7 @@ fn main() -> std::io::Result<()> {
             params.k,
             params.iterations,
             params.noopt,
+            params.budget,
         ),
         6 => eval_cov::<6>(
             tf_names,
@@ -85,6 +177,7 @@ fn main() -> std::io::Result<()> {
             params.k,
             params.iterations,
             params.noopt,
+            params.budget,
         ),
         7 => eval_cov::<7>(
             tf_names,
@@ -93,6 +186,7 @@ fn main() -> std::io::Result<()> {
             params.k,
             params.iterations,
             params.noopt,
+            params.budget,
         ),
         8 => eval_cov::<8>(
             tf_names,
@@ -101,6 +195,7 @@ fn main() -> std::io::Result<()> {
             params.k,
             params.iterations,
             params.noopt,
+            params.budget,
         ),
         9 => eval_cov::<9>(
             tf_names,
@@ -109,6 +204,7 @@ fn main() -> std::io::Result<()> {
             params.k,
             params.iterations,
             params.noopt,
+            params.budget,
         ),
         10 => eval_cov::<10>(
             tf_names,
@@ -117,6 +213,7 @@ fn main() -> std::io::Result<()> {
             params.k,
             params.iterations,
             params.noopt,
+            params.budget,
         ),
         11 => eval_cov::<11>(
             tf_names,
@@ -125,6 +222,7 @@ fn main() -> std::io::Result<()> {
             params.k,
             params.iterations,
             params.noopt,
+            params.budget,
         ),
         12 => eval_cov::<12>(
             tf_names,
@@ -133,6 +231,7 @@ fn main() -> std::io::Result<()> {
             params.k,
             params.iterations,
             params.noopt,
+            params.budget,
         ),
         _ => panic!("l must be a value between 1 and 32"),
     };
@@ -142,82 +241,308 @@ fn main() -> std::io::Result<()> {
 
 type Names = Vec<String>;
 
+/// Header of the optional column with the weights of the genes
+const WEIGHT_COLUMN: &str = "weight";
+
+/// Name of the optional row with the probing costs of the TFs
+const COST_ROW: &str = "cost";
+
 fn parse_file(
     path: &PathBuf,
-    num: usize,
+    seed: u64,
+    family: &DistributionFamily,
+    dispersion: f64,
 ) -> Result<(Names, Names, BipartiteRegulatorProbing), Error> {
     let error = |msg| Err(Error::other(msg));
 
+    if matches!(
+        family,
+        DistributionFamily::UNIFORM | DistributionFamily::EMPIRICAL
+    ) {
+        return error("Distribution family is not supported for networks!");
+    }
+
     let mut lines = BufReader::new(File::open(path)?)
         .lines()
         .filter_map(|x| -> Option<String> { x.ok() });
 
-    let tf_names: Vec<String> = if let Some(header) = lines.next() {
-        header.split('\t').skip(2).map(|s| s.to_string()).collect()
-    } else {
-        return error("Empty file!");
+    let header = match lines.next() {
+        Some(header) => header,
+        None => return error("Empty file!"),
     };
 
+    // An optional column `weight` after `#CREs` holds the importance of every gene
+    let has_weights = header.split('\t').nth(2) == Some(WEIGHT_COLUMN);
+    let tf_names: Vec<String> = header
+        .split('\t')
+        .skip(if has_weights { 3 } else { 2 })
+        .map(|s| s.to_string())
+        .collect();
+
     let na = tf_names.len();
 
     let mut gen_names: Vec<String> = Vec::new();
+    let mut gen_weights: Vec<f64> = Vec::new();
+    let mut tf_costs: Option<Vec<f64>> = None;
+    // Edges (gene, distribution) of every TF. Edges without binding sites (n = 0 or p = 0) are always 0 and omitted
+    let mut edges: Vec<Vec<(usize, WeightedDistribution)>> = vec![Vec::new(); na];
+
+    for (line_number, l) in lines.enumerate() {
+        // Line numbers start at 1 and the header is line 1
+        let line_error =
+            |msg: String| Err(Error::other(format!("Line {}: {}", line_number + 2, msg)));
+
+        let mut fields = l.split('\t');
+        let name = fields.next().unwrap_or_default();
+
+        // The row `cost` holds the probing cost of every TF in its columns (#CREs and weight are ignored)
+        if name == COST_ROW {
+            let costs = fields
+                .skip(if has_weights { 2 } else { 1 })
+                .map(|f| match f.parse::<f64>() {
+                    Ok(c) if c.is_finite() && c > 0.0 => Ok(c),
+                    _ => Err(format!("Could not parse positive cost {}!", f)),
+                })
+                .collect::<Result<Vec<f64>, String>>();
+
+            match costs {
+                Ok(costs) if costs.len() == na => tf_costs = Some(costs),
+                Ok(costs) => {
+                    return line_error(format!("Expected {} costs, found {}!", na, costs.len()))
+                }
+                Err(msg) => return line_error(msg),
+            }
+            continue;
+        }
+
+        let b = gen_names.len();
+        gen_names.push(name.to_string());
 
-    let mut vs = 0u64;
-    let binom_values: Vec<Vec<(u64, f64)>> = lines
-        .map(|l| -> Vec<(u64, f64)> {
-            let mut fields = l.split('\t');
-            gen_names.push(fields.next().unwrap().to_string());
-            let n = fields.next().unwrap().parse::<u64>().unwrap();
-            if n > vs {
-                vs = n;
+        let n = match fields.next().map(|f| f.parse::<u64>()) {
+            Some(Ok(n)) => n,
+            _ => return line_error("Could not parse #CREs!".to_string()),
+        };
+
+        if has_weights {
+            match fields.next().map(|f| f.parse::<f64>()) {
+                Some(Ok(w)) if w.is_finite() && w >= 0.0 => gen_weights.push(w),
+                _ => return line_error("Could not parse non-negative weight!".to_string()),
             }
-            fields
-                .map(|f| -> (u64, f64) {
-                    let p = f.parse::<f64>().unwrap();
-                    (n, p)
-                })
-                .collect()
-        })
-        .collect();
+        }
+
+        let mut row = Vec::with_capacity(na);
+        for f in fields {
+            match f.parse::<f64>() {
+                Ok(p) => row.push(p),
+                Err(_) => return line_error(format!("Could not parse probability {}!", f)),
+            }
+        }
+
+        if row.len() != na {
+            return line_error(format!(
+                "Expected {} probabilities, found {}!",
+                na,
+                row.len()
+            ));
+        }
+
+        for (a, p) in row.into_iter().enumerate() {
+            if n == 0 || p == 0.0 {
+                continue;
+            }
+
+            let edge = match network_distribution(family, n, p, dispersion) {
+                Ok(edge) => edge,
+                Err(e) => {
+                    return line_error(format!("Edge ({}, {}): {}", tf_names[a], gen_names[b], e))
+                }
+            };
+            edges[a].push((b, edge));
+        }
+    }
 
     let nb = gen_names.len();
 
-    let binom_weights = |n: u64, p: f64| -> Vec<f64> {
-        let binom = Binomial::new(p, n).unwrap();
+    let mut bpr = BipartiteRegulatorProbing::new_sparse(na, nb, edges, seed);
+    if has_weights {
+        bpr.set_position_weights(gen_weights);
+    }
+    if let Some(costs) = tf_costs {
+        bpr.set_regulator_costs(costs);
+    }
 
-        let mut total_weight = 0.0;
-        let mut weights: Vec<f64> = (0..(vs - 1))
-            .map(|i| {
-                let p = binom.pmf(i);
-                total_weight += p;
-                p
-            })
-            .collect();
+    Ok((tf_names, gen_names, bpr))
+}
 
-        weights.push(1.0 - total_weight);
-        weights
-    };
+/// Split a column name `SYMBOL(MOTIF)` into the gene symbol and the motif ID
+fn split_motif(name: &str) -> (&str, Option<&str>) {
+    match name.strip_suffix(')').and_then(|n| n.rsplit_once('(')) {
+        Some((symbol, motif)) => (symbol, Some(motif)),
+        None => (name, None),
+    }
+}
 
-    let rng = &mut rand::rng();
+/// Merge all columns with the same gene symbol into one TF named `SYMBOL(MOTIF1,MOTIF2,...)`,
+/// so the motif IDs stay traceable in the output. TFs with a single column keep their name.
+fn merge_motifs(
+    tf_names: &[String],
+    bpr: &BipartiteRegulatorProbing,
+    rule: &MergeRule,
+) -> (Names, BipartiteRegulatorProbing) {
+    let mut symbols: Vec<&str> = Vec::new();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut symbol_index: HashMap<&str, usize> = HashMap::new();
+
+    for (a, name) in tf_names.iter().enumerate() {
+        let symbol = split_motif(name).0;
+        let g = *symbol_index.entry(symbol).or_insert_with(|| {
+            symbols.push(symbol);
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[g].push(a);
+    }
 
-    let edges = (0..na)
-        .map(|a| -> Vec<WeightedDistribution> {
-            (0..nb)
-                .map(|b| -> WeightedDistribution {
-                    let (n, p) = binom_values[b][a];
-                    WeightedDistribution::new(rng, &binom_weights(n, p), num)
-                })
-                .collect()
+    let names = symbols
+        .iter()
+        .zip(groups.iter())
+        .map(|(symbol, group)| match group.as_slice() {
+            [a] => tf_names[*a].clone(),
+            _ => format!(
+                "{}({})",
+                symbol,
+                group
+                    .iter()
+                    .map(|a| split_motif(&tf_names[*a]).1.unwrap_or(&tf_names[*a]))
+                    .collect::<Vec<&str>>()
+                    .join(",")
+            ),
         })
         .collect();
 
-    Ok((
-        tf_names,
-        gen_names,
-        BipartiteRegulatorProbing::new(na, nb, vs as usize, edges),
+    (names, bpr.merge_regulators(&groups, &rule.reduction()))
+}
+
+/// Parse the groups of the TFs from lines `TF<TAB>group[<TAB>capacity]`. TFs not in the file are unconstrained.
+/// A gene symbol assigns all TFs with this symbol (e.g. all motif variants) to the group.
+fn parse_groups(
+    path: &PathBuf,
+    tf_names: &[String],
+    default_capacity: usize,
+) -> Result<GroupConstraint, Error> {
+    // A TF is referred to by its name, its gene symbol or the name of any of its (merged) motif variants
+    let mut tf_index: HashMap<String, Vec<usize>> = HashMap::new();
+    for (a, name) in tf_names.iter().enumerate() {
+        let (symbol, motifs) = split_motif(name);
+        let mut keys: Vec<String> = vec![name.clone(), symbol.to_string()];
+        keys.extend(
+            motifs
+                .into_iter()
+                .flat_map(|m| m.split(','))
+                .map(|m| format!("{}({})", symbol, m)),
+        );
+        keys.sort_unstable();
+        keys.dedup();
+
+        for key in keys {
+            tf_index.entry(key).or_default().push(a);
+        }
+    }
+
+    let mut groups: Vec<Option<usize>> = vec![None; tf_names.len()];
+    let mut group_index: HashMap<String, usize> = HashMap::new();
+    let mut capacities: Vec<Option<usize>> = Vec::new();
+
+    for (line_number, l) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let l = l?;
+        let line_error =
+            |msg: String| Err(Error::other(format!("Line {}: {}", line_number + 1, msg)));
+
+        if l.trim().is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = l.split('\t').collect();
+        if !(2..=3).contains(&fields.len()) {
+            return line_error("Expected TF<TAB>group[<TAB>capacity]!".to_string());
+        }
+
+        let regulators = match tf_index.get(fields[0]) {
+            Some(regulators) => regulators,
+            None => return line_error(format!("Unknown TF {}!", fields[0])),
+        };
+
+        let next_group = group_index.len();
+        let g = *group_index
+            .entry(fields[1].to_string())
+            .or_insert(next_group);
+        if g == capacities.len() {
+            capacities.push(None);
+        }
+        for a in regulators {
+            groups[*a] = Some(g);
+        }
+
+        if let Some(field) = fields.get(2) {
+            let capacity = match field.parse::<usize>() {
+                Ok(c) => c,
+                Err(_) => return line_error(format!("Could not parse capacity {}!", field)),
+            };
+            if capacities[g].is_some_and(|c| c != capacity) {
+                return line_error(format!("Conflicting capacities of group {}!", fields[1]));
+            }
+            capacities[g] = Some(capacity);
+        }
+    }
+
+    Ok(GroupConstraint::new(
+        groups,
+        capacities
+            .into_iter()
+            .map(|c| c.unwrap_or(default_capacity))
+            .collect(),
     ))
 }
 
+/// Distribution of an edge with `n` CREs each bound with probability `p`.
+/// Every edge gets its own support `{0, ..., n}` and all families have mean (about) `n * p`.
+fn network_distribution(
+    family: &DistributionFamily,
+    n: u64,
+    p: f64,
+    dispersion: f64,
+) -> Result<WeightedDistribution, DistributionError> {
+    let mean = n as f64 * p;
+    let vs = n as usize + 1;
+
+    let invalid_dispersion = match family {
+        DistributionFamily::BETABINOMIAL => !(dispersion > 0.0 && dispersion < 1.0),
+        DistributionFamily::NEGBINOMIAL => !(dispersion > 0.0 && dispersion.is_finite()),
+        _ => false,
+    };
+    if invalid_dispersion {
+        return Err(DistributionError::InvalidParameter {
+            name: "dispersion",
+            value: dispersion,
+        });
+    }
+
+    match family {
+        DistributionFamily::BETABINOMIAL if p > 0.0 && p < 1.0 => {
+            let scale = (1.0 - dispersion) / dispersion;
+            WeightedDistribution::beta_binomial(n, p * scale, (1.0 - p) * scale)
+        }
+        DistributionFamily::POISSON => WeightedDistribution::poisson(mean, vs),
+        DistributionFamily::GEOMETRIC => WeightedDistribution::geometric(1.0 / (1.0 + mean), vs),
+        DistributionFamily::NEGBINOMIAL => {
+            let r = 1.0 / dispersion;
+            WeightedDistribution::negative_binomial(r, r / (r + mean), vs)
+        }
+        DistributionFamily::BERNOULLI => WeightedDistribution::bernoulli_scaled(p, n as f64),
+        _ => WeightedDistribution::binomial(n, p),
+    }
+}
+
 fn eval_cov<const NUM: usize>(
     tfs: Vec<String>,
     _gens: Vec<String>,
@@ -225,53 +550,65 @@ fn eval_cov<const NUM: usize>(
     k: usize,
     num: usize,
     exclude_opt: bool,
+    budget: Option<f64>,
 ) where
     [String; NUM]: Serialize,
 {
-    bpr.compute_namp_cov_policy(k, NUM);
+    let policies = BipartiteRegulatorProbing::policies(&PolicyOptions::default());
+    if budget.is_none() {
+        for policy in &policies {
+            policy.prepare(&mut bpr, k, NUM);
+        }
+    }
 
     (0..num).for_each(|i| {
         let ins = bpr.create_instance(i);
 
-        let res = if exclude_opt {
-            let namp = ins.find_top_tuples::<NUM>(bpr.get_policy(k, NUM).unwrap());
+        let mut tuples = BTreeMap::new();
+        let mut time = BTreeMap::new();
+        let mut spent = BTreeMap::new();
+        for policy in &policies {
+            let name = format!("{:?}", policy.algorithm()).to_lowercase();
 
-            let timer = Instant::now();
-            let amp = ins.adaptive_policy_regulators(k, NUM);
-            let time = AlgoTimes(
-                timer.elapsed().as_secs_f64(),
-                bpr.get_policy_time(k, NUM).unwrap(),
-            );
-
-            let amp = ins.find_top_tuples::<NUM>(&amp);
+            if policy.is_offline() && exclude_opt {
+                tuples.insert(name, TopTuples::default());
+                continue;
+            }
 
-            TfNetworkResult {
-                k,
-                opt: Default::default(),
-                namp: (namp, tfs.as_ref()).into(),
-                amp: (amp, tfs.as_ref()).into(),
-                time,
+            let (probed, probe_time) = match budget {
+                Some(budget) if !policy.is_offline() => {
+                    match policy.probe_budget(&ins, budget, NUM) {
+                        Some(res) => res,
+                        None => continue,
+                    }
+                }
+                _ => policy.probe(&ins, k, NUM),
+            };
+
+            // Too few TFs probed within the budget to choose from
+            if probed.len() <= NUM {
+                tuples.insert(name.clone(), TopTuples::default());
+            } else {
+                tuples.insert(
+                    name.clone(),
+                    (ins.find_top_tuples::<NUM>(&probed), tfs.as_ref()).into(),
+                );
             }
-        } else {
-            let opt = ins.top_opt_tuples::<NUM>();
-            let namp = ins.find_top_tuples::<NUM>(bpr.get_policy(k, NUM).unwrap());
-
-            let timer = Instant::now();
-            let amp = ins.adaptive_policy_regulators(k, NUM);
-            let time = AlgoTimes(
-                timer.elapsed().as_secs_f64(),
-                bpr.get_policy_time(k, NUM).unwrap(),
-            );
-
-            let amp = ins.find_top_tuples::<NUM>(&amp);
-
-            TfNetworkResult {
-                k,
-                opt: (opt, tfs.as_ref()).into(),
-                namp: (namp, tfs.as_ref()).into(),
-                amp: (amp, tfs.as_ref()).into(),
-                time,
+            if !policy.is_offline() {
+                if budget.is_some() {
+                    spent.insert(name.clone(), bpr.get_probing_cost(&probed));
+                }
+                time.insert(name, probe_time);
             }
+        }
+
+        let res = TfNetworkResult {
+            k,
+            seed: bpr.seed,
+            tuples,
+            time,
+            budget,
+            spent,
         };
 
         println!("{}", serde_json::to_string(&res).unwrap());
@@ -279,7 +616,7 @@ fn eval_cov<const NUM: usize>(
 }
 
 #[derive(Debug, Serialize)]
-struct ConstantTFResult<const NUM: usize>([String; NUM], usize)
+struct ConstantTFResult<const NUM: usize>([String; NUM], f64)
 where
     [String; NUM]: Serialize;
 
@@ -288,11 +625,11 @@ struct TopTuples<const NUM: usize>([ConstantTFResult<NUM>; NUM_TOP_TUPLES])
 where
     [String; NUM]: Serialize;
 
-impl<const NUM: usize> From<([([usize; NUM], usize); NUM_TOP_TUPLES], &[String])> for TopTuples<NUM>
+impl<const NUM: usize> From<([([usize; NUM], f64); NUM_TOP_TUPLES], &[String])> for TopTuples<NUM>
 where
     [String; NUM]: Serialize,
 {
-    fn from(value: ([([usize; NUM], usize); NUM_TOP_TUPLES], &[String])) -> Self {
+    fn from(value: ([([usize; NUM], f64); NUM_TOP_TUPLES], &[String])) -> Self {
         TopTuples(value.0.map(|(arr, val)| -> ConstantTFResult<NUM> {
             ConstantTFResult(arr.map(|x| value.1[x].clone()), val)
         }))
@@ -305,21 +642,135 @@ where
 {
     fn default() -> Self {
         let names = [String::new()];
-        ([([0usize; NUM], 0usize); NUM_TOP_TUPLES], names.as_ref()).into()
+        ([([0usize; NUM], 0.0); NUM_TOP_TUPLES], names.as_ref()).into()
     }
 }
 
-#[derive(Debug, Serialize)]
-struct AlgoTimes(f64, f64);
-
 #[derive(Serialize, Debug)]
 struct TfNetworkResult<const NUM: usize>
 where
     [String; NUM]: Serialize,
 {
     k: usize,
-    opt: TopTuples<NUM>,
-    amp: TopTuples<NUM>,
-    namp: TopTuples<NUM>,
-    time: AlgoTimes,
+    seed: u64,
+    /// Top tuples of every algorithm keyed by its name in lowercase
+    #[serde(flatten)]
+    tuples: BTreeMap<String, TopTuples<NUM>>,
+    /// Time of every probing policy (without OPT)
+    time: BTreeMap<String, f64>,
+    /// Budget of the probing policies (if they are not limited to k TFs)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    budget: Option<f64>,
+    /// Total probing cost of every probing policy within the budget
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    spent: BTreeMap<String, f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse a network given by its lines with the binomial family
+    fn parse_lines(lines: &[&str]) -> Result<(Names, Names, BipartiteRegulatorProbing), Error> {
+        let path = std::env::temp_dir().join(format!(
+            "bpr_network_{}_{:?}.tsv",
+            std::process::id(),
+            std::thread::current().id()
+        ));
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        let result = parse_file(&path, 0, &DistributionFamily::BINOMIAL, 0.5);
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn cost_row() {
+        let header = "gene\t#CREs\tA\tB\tC";
+        let gene = "g\t2\t0.5\t0.0\t0.25";
+
+        let (_, genes, bpr) = parse_lines(&[header, gene, "cost\t0\t1.5\t2\t0.25"]).unwrap();
+        assert_eq!(genes, vec!["g".to_string()]);
+        assert_eq!(bpr.get_probing_cost(&[0, 1, 2]), 3.75);
+        assert_eq!(bpr.get_regulator_cost(1), 2.0);
+
+        // The weight column is skipped as well
+        let lines = [
+            "gene\t#CREs\tweight\tA\tB",
+            "g\t2\t1.5\t0.5\t0.5",
+            "cost\t0\t0\t3\t4",
+        ];
+        let (_, _, bpr) = parse_lines(&lines).unwrap();
+        assert_eq!(bpr.get_regulator_cost(0), 3.0);
+        assert_eq!(bpr.get_position_weight(0), 1.5);
+
+        // Without a cost row, all costs are 1
+        let (_, _, bpr) = parse_lines(&[header, gene]).unwrap();
+        assert_eq!(bpr.get_probing_cost(&[0, 1, 2]), 3.0);
+
+        for costs in [
+            "1\t2",
+            "1\t2\t3\t4",
+            "1\t0\t1",
+            "1\t-2\t1",
+            "1\tx\t1",
+            "1\tinf\t1",
+        ] {
+            let row = format!("cost\t0\t{}", costs);
+            assert!(parse_lines(&[header, gene, &row]).is_err(), "{}", row);
+        }
+    }
+
+    #[test]
+    fn bad_rows_are_errors() {
+        let header = "gene\t#CREs\tA\tB";
+        assert!(parse_lines(&[header, "g\t2\t0.5\t0.25"]).is_ok());
+
+        for row in [
+            "g\t2\t1.5\t0.25",
+            "g\t2\t-0.5\t0.25",
+            "g\t2\tNaN\t0.25",
+            "g\t2\tx\t0.25",
+            "g\t2\t0.5",
+            "g\t2\t0.5\t0.25\t0.25",
+            "g\t-2\t0.5\t0.25",
+            "g",
+        ] {
+            let error = parse_lines(&[header, row]).unwrap_err().to_string();
+            assert!(error.starts_with("Line 2: "), "{}: {}", row, error);
+        }
+
+        assert!(parse_lines(&[]).is_err());
+    }
+
+    #[test]
+    fn network_distribution_dispersion() {
+        let families = [
+            (DistributionFamily::BETABINOMIAL, [0.0, 1.0, -0.5, f64::NAN]),
+            (
+                DistributionFamily::NEGBINOMIAL,
+                [0.0, -1.0, f64::INFINITY, f64::NAN],
+            ),
+        ];
+        for (family, dispersions) in &families {
+            for dispersion in dispersions {
+                assert!(matches!(
+                    network_distribution(family, 10, 0.3, *dispersion),
+                    Err(DistributionError::InvalidParameter {
+                        name: "dispersion",
+                        ..
+                    })
+                ));
+            }
+
+            // Valid dispersions keep the mean n * p up to the truncation at n
+            let dist = network_distribution(family, 10, 0.3, 0.2).unwrap();
+            assert!(dist.get_values().iter().all(|v| *v <= 10.0));
+            assert!(dist.expected_value() <= 3.0 + 1e-9 && dist.expected_value() > 2.5);
+        }
+
+        // The dispersion is ignored by the other families
+        for family in [DistributionFamily::BINOMIAL, DistributionFamily::POISSON] {
+            assert!(network_distribution(&family, 10, 0.3, -1.0).is_ok());
+        }
+    }
 }